@@ -0,0 +1,4 @@
+-- Dager vi ikkje treng rom, bookinga på disse dagan avbestilles automatisk
+CREATE TABLE IF NOT EXISTS cancelled_days (
+    day DATE PRIMARY KEY
+);
@@ -0,0 +1,8 @@
+-- Romkatalogen fra TP, bare rom vi faktisk kan book
+CREATE TABLE IF NOT EXISTS rooms (
+    name VARCHAR PRIMARY KEY,
+    building_name VARCHAR NOT NULL,
+    size INTEGER NOT NULL,
+    can_book BOOLEAN NOT NULL,
+    fetched_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
@@ -0,0 +1,8 @@
+-- Siste timeplan vi henta fra TP for hvert rom, sånn at alle som treng den kan dele samme kopi
+CREATE TABLE IF NOT EXISTS room_schedules (
+    room VARCHAR PRIMARY KEY,
+    events JSONB NOT NULL,
+    from_date DATE NOT NULL,
+    to_date DATE NOT NULL,
+    fetched_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
@@ -0,0 +1,15 @@
+-- Alt booking-roboten har gjort, én rad per ting som skjedde. Radan fra samme kjøring har samme run_started.
+CREATE TABLE IF NOT EXISTS booking_attempts (
+    id BIGSERIAL PRIMARY KEY,
+    run_started TIMESTAMPTZ NOT NULL,
+    logged_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    kind VARCHAR NOT NULL,
+    slot_start TIMESTAMP,
+    slot_end TIMESTAMP,
+    room VARCHAR,
+    login INTEGER,
+    detail TEXT NOT NULL DEFAULT ''
+);
+
+CREATE INDEX IF NOT EXISTS booking_attempts_run_started ON booking_attempts (run_started);
+CREATE INDEX IF NOT EXISTS booking_attempts_slot_start ON booking_attempts (slot_start);
@@ -0,0 +1,8 @@
+-- Hver gruppe har sine egne avlyste dager og sin egen logg. Det som fantes fra før høre til HelgaSangern.
+ALTER TABLE cancelled_days ADD COLUMN gruppe VARCHAR NOT NULL DEFAULT 'helgasangern';
+ALTER TABLE cancelled_days DROP CONSTRAINT cancelled_days_pkey;
+ALTER TABLE cancelled_days ADD PRIMARY KEY (gruppe, day);
+ALTER TABLE cancelled_days ALTER COLUMN gruppe DROP DEFAULT;
+
+ALTER TABLE booking_attempts ADD COLUMN gruppe VARCHAR NOT NULL DEFAULT 'helgasangern';
+ALTER TABLE booking_attempts ALTER COLUMN gruppe DROP DEFAULT;
@@ -0,0 +1,12 @@
+-- Ekstra økter noen i en gruppe har bedt om via bestillingsskjemaet. Bookes av den vanlige bookingkjøringa.
+CREATE TABLE IF NOT EXISTS booking_requests (
+    id BIGSERIAL PRIMARY KEY,
+    gruppe VARCHAR NOT NULL,
+    start TIMESTAMP NOT NULL,
+    slutt TIMESTAMP NOT NULL,
+    min_kapasitet INTEGER NOT NULL,
+    status VARCHAR NOT NULL,
+    room VARCHAR,
+    detail TEXT NOT NULL DEFAULT '',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
@@ -0,0 +1,3 @@
+-- Timeplanan lagres nå under institusjon/byggkode/rom i stedet for bare romnavnet, sånn at like romnavn i forskjellige bygg ikkje blandes.
+-- Dem gamle hentes bare på nytt.
+DELETE FROM room_schedules;
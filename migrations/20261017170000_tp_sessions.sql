@@ -0,0 +1,14 @@
+-- Feide sessionan te kontoan, med når vi fikk dem og når TP sist godtok dem. Se sesjon.rs.
+CREATE TABLE IF NOT EXISTS tp_sessions (
+    brukernavn VARCHAR PRIMARY KEY,
+    cookie VARCHAR NOT NULL,
+    obtained_at TIMESTAMPTZ NOT NULL,
+    validated_at TIMESTAMPTZ NOT NULL
+);
+
+-- Cookiesa lå i key_value før. Vi vet ikkje hvor gamle dem e, så dem fornyes første gang refreshSessions kjøre.
+INSERT INTO tp_sessions (brukernavn, cookie, obtained_at, validated_at)
+    SELECT substring(key FROM length('TP_COOKIE_KEY_') + 1), value, to_timestamp(0), to_timestamp(0)
+    FROM key_value WHERE key LIKE 'TP\_COOKIE\_KEY\_%' AND value <> ''
+    ON CONFLICT (brukernavn) DO NOTHING;
+DELETE FROM key_value WHERE key LIKE 'TP\_COOKIE\_KEY\_%';
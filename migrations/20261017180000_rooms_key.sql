@@ -0,0 +1,8 @@
+-- Et rom e institusjon + byggkode + navn, så like romnavn i forskjellige bygg kan ligg i katalogen samtidig.
+-- Romman som ligg der fra før e fra Helgasetr på NTNU.
+ALTER TABLE rooms ADD COLUMN IF NOT EXISTS institusjon VARCHAR NOT NULL DEFAULT 'ntnu';
+ALTER TABLE rooms ADD COLUMN IF NOT EXISTS byggkode VARCHAR NOT NULL DEFAULT '250';
+ALTER TABLE rooms ALTER COLUMN institusjon DROP DEFAULT;
+ALTER TABLE rooms ALTER COLUMN byggkode DROP DEFAULT;
+ALTER TABLE rooms DROP CONSTRAINT IF EXISTS rooms_pkey;
+ALTER TABLE rooms ADD PRIMARY KEY (institusjon, byggkode, name);
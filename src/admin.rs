@@ -0,0 +1,342 @@
+use chrono::{DateTime, Duration as ChronoDuration, Locale, NaiveDate, NaiveTime, Utc};
+use maud::html;
+use rocket::{form::{Form, FromForm}, get, http::{Cookie, CookieJar, Status}, post, request::{FromRequest, Outcome}, response::{content::{RawHtml, RawJson}, Redirect}, routes, uri, Request, Route, State};
+use shuttle_runtime::SecretStore;
+
+use crate::{claimRoom, getCancelledDays, getClientAndBookings, page, releaseCancelledBookings, GetSet};
+use crate::historikk::{self, Attempt};
+use crate::sesjon::{self, Login, Session};
+use crate::gruppe::{self, Group};
+use crate::tp::{ClientMethods, TpBooking};
+
+// Når man logge inn med ADMIN_PASSORD får man en tilfeldig token i cookien, og tokenen lagres i key_value
+// med når den ble laga. Sånn havne aldri passordet i en cookie.
+const ADMIN_COOKIE: &str = "admin_token";
+const KEY_ADMIN_TOKEN_PREFIX: &str = "ADMIN_TOKEN_";
+
+/// Hvor lenge man e logga inn som admin
+const ADMIN_TOKEN_MAX_AGE: ChronoDuration = ChronoDuration::days(30);
+
+/// Request guard for admin sidene. Sjekke at tokenen i cookien e en vi har gitt ut, og at den ikkje e for gammel.
+pub struct Admin;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Admin {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let (Some(cookie), Some(pool)) = (req.cookies().get(ADMIN_COOKIE), req.rocket().state::<sqlx::PgPool>()) else {
+            return Outcome::Forward(Status::Unauthorized);
+        };
+        let created = pool.get(&(KEY_ADMIN_TOKEN_PREFIX.to_string() + cookie.value())).await;
+        match DateTime::parse_from_rfc3339(&created) {
+            Ok(created) if Utc::now() - created.with_timezone(&Utc) < ADMIN_TOKEN_MAX_AGE => Outcome::Success(Admin),
+            _ => Outcome::Forward(Status::Unauthorized),
+        }
+    }
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![admin, adminLogin, login, cancelBooking, modifyBooking, cancelDay, uncancelDay, attempts, attemptsJson]
+}
+
+/// Oversikt over alle bookingan te alle gruppene, med knappa for å avbestill dem og avlys heile dager
+#[get("/admin")]
+async fn admin(
+    _admin: Admin,
+    groups: &State<Vec<Group>>,
+    pool: &State<sqlx::PgPool>
+) -> RawHtml<String> {
+    let mut sections = Vec::new();
+    for group in groups.iter() {
+        let mut bookings: Vec<(usize, TpBooking)> = Vec::new();
+        let mut errors: Vec<String> = Vec::new();
+        let mut sessions: Vec<(usize, Option<Session>)> = Vec::new();
+        for account in group.accounts.iter() {
+            match getClientAndBookings(account, &group.policy, pool, Login::Background).await {
+                Ok((_client, booking)) => bookings.extend(booking.into_iter().map(|b| (account.index, b))),
+                Err(e) => errors.push(format!("Fikk ikkje henta bookingan te login {}: {}", account.index, e)),
+            }
+            sessions.push((account.index, sesjon::load(pool, &account.brukernavn).await));
+        }
+        bookings.sort_by_key(|(_, b)| b.booked);
+
+        let mut cancelledDays: Vec<NaiveDate> = getCancelledDays(pool, &group.slug).await.into_iter().collect();
+        cancelledDays.sort();
+        sections.push((group, bookings, errors, sessions, cancelledDays));
+    }
+
+    let time = |t: &chrono::DateTime<Utc>| t.with_timezone(&chrono_tz::Europe::Oslo).format("%a %e. %H:%M").to_string();
+    page("Admin", html!{
+        @for (group, bookings, errors, sessions, cancelledDays) in &sections {
+            h2 class="text-2xl pt-3" { (group.policy.navn) }
+            h2 class="text-lg pt-3" { "Bookinga" }
+            @for error in errors {
+                div class="truncate" { (error) }
+            }
+            @for (index, session) in sessions {
+                div class="truncate" {
+                    "Login " (index) ": "
+                    @if let Some(session) = session {
+                        "logga inn " (time(&session.obtained_at)) ", sist godtatt av TP " (time(&session.validated_at))
+                    } @else {
+                        "ikkje logga inn enda"
+                    }
+                }
+            }
+            @for (clientIndex, booking) in bookings {
+                @let roomName = booking.room();
+                form class="flex flex-row justify-around h-12 pt-3" method="post" action=(uri!(cancelBooking)) {
+                    a href=(uri!(crate::roomRedirect(&group.slug, roomName))) { (roomName) }
+                    span { (booking.firstday.format_localized("%a %e.", Locale::nb_NO)) " " (booking.tid) }
+                    input type="hidden" name="gruppe" value=(group.slug);
+                    input type="hidden" name="login" value=(clientIndex);
+                    input type="hidden" name="id" value=(booking.id);
+                    button type="submit" { "Avbestill" }
+                }
+                form class="flex flex-row justify-around h-12" method="post" action=(uri!(modifyBooking)) {
+                    input type="text" name="rom" value=(roomName) required;
+                    input type="date" name="dato" value=(booking.firstday.format("%F")) required;
+                    input type="time" name="fra" value=(booking.booked.format("%H:%M")) required;
+                    input type="time" name="til" value=(booking.end().map(|end| end.format("%H:%M").to_string()).unwrap_or_default()) required;
+                    input type="hidden" name="gruppe" value=(group.slug);
+                    input type="hidden" name="login" value=(clientIndex);
+                    input type="hidden" name="id" value=(booking.id);
+                    button type="submit" { "Endre" }
+                }
+            }
+
+            h2 class="text-lg pt-3" { "Avlyste dager" }
+            @for day in cancelledDays {
+                form class="flex flex-row justify-around h-12 pt-3" method="post" action=(uri!(uncancelDay)) {
+                    span { (day.format_localized("%A %e. %B", Locale::nb_NO)) }
+                    input type="hidden" name="gruppe" value=(group.slug);
+                    input type="hidden" name="day" value=(day.format("%F"));
+                    button type="submit" { "Angre" }
+                }
+            }
+            form class="flex flex-row justify-around h-12 pt-3" method="post" action=(uri!(cancelDay)) {
+                input type="hidden" name="gruppe" value=(group.slug);
+                input type="date" name="day" min=(Utc::now().date_naive().format("%F"));
+                button type="submit" { "Avlys dag" }
+            }
+        }
+        div class="pt-3 pb-12" { a href=(uri!(attempts(None::<String>))) { "Se hva roboten har gjort" } }
+    })
+}
+
+/// Gruppa et form gjelde, eller BadRequest om den ikkje finnes
+fn findGroup<'a>(groups: &'a [Group], slug: &str) -> Result<&'a Group, (Status, String)> {
+    gruppe::find(groups, slug).ok_or((Status::BadRequest, format!("Ukjent gruppe {}", slug)))
+}
+
+/// Om man ikkje e logga inn som admin får man et login form i stedet
+#[get("/admin", rank = 2)]
+fn adminLogin() -> RawHtml<String> {
+    page("Admin", html!{
+        form class="flex flex-row justify-around h-12 pt-3" method="post" action=(uri!(login)) {
+            input type="password" name="passord";
+            button type="submit" { "Logg inn" }
+        }
+    })
+}
+
+#[derive(FromForm)]
+struct LoginForm {
+    passord: String,
+}
+
+/// Med riktig passord får man en ny token, med feil passord kommer man bare tebake te login formet
+#[post("/admin/login", data = "<form>")]
+async fn login(
+    form: Form<LoginForm>,
+    cookies: &CookieJar<'_>,
+    secretStore: &State<SecretStore>,
+    pool: &State<sqlx::PgPool>
+) -> Redirect {
+    // Gamle innlogginga hadde passordet i cookien
+    cookies.remove("admin_passord");
+    if secretStore.get("ADMIN_PASSORD").is_some_and(|passord| passord == form.passord) {
+        let token: String = (0..32).map(|_| format!("{:02x}", rand::random::<u8>())).collect();
+        pool.set(&(KEY_ADMIN_TOKEN_PREFIX.to_string() + &token), &Utc::now().to_rfc3339()).await;
+        cookies.add(Cookie::build((ADMIN_COOKIE, token))
+            .http_only(true)
+            .max_age(rocket::time::Duration::days(ADMIN_TOKEN_MAX_AGE.num_days())));
+    }
+    Redirect::to(uri!(admin))
+}
+
+#[derive(FromForm)]
+struct CancelBookingForm {
+    gruppe: String,
+    login: usize,
+    id: String,
+}
+
+/// Avbestill en enkelt booking med brukeren som eie den
+#[post("/admin/avbestill", data = "<form>")]
+async fn cancelBooking(
+    _admin: Admin,
+    form: Form<CancelBookingForm>,
+    groups: &State<Vec<Group>>,
+    pool: &State<sqlx::PgPool>
+) -> Result<Redirect, (Status, String)> {
+    let group = findGroup(groups, &form.gruppe)?;
+    let account = group.accounts.get(form.login).ok_or((Status::BadRequest, format!("Ukjent login {}", form.login)))?;
+    let (client, _bookings) = getClientAndBookings(account, &group.policy, pool, Login::Background).await
+        .map_err(|e| (Status::BadGateway, format!("Fikk ikkje kontakt med TP: {}", e)))?;
+    client.cancelBooking(&group.policy.institusjon, &form.id).await
+        .map_err(|e| (Status::BadGateway, format!("Fikk ikkje avbestilt: {}", e)))?;
+    println!("Admin avbestilte booking {} for {} client {}", form.id, group.slug, form.login);
+    Ok(Redirect::to(uri!(admin)))
+}
+
+#[derive(FromForm)]
+struct ModifyBookingForm {
+    gruppe: String,
+    login: usize,
+    id: String,
+    rom: String,
+    dato: String,
+    fra: String,
+    til: String,
+}
+
+/// Flytt en booking te et anna rom eller tidspunkt, med samme login og samme navn
+#[post("/admin/endre", data = "<form>")]
+async fn modifyBooking(
+    _admin: Admin,
+    form: Form<ModifyBookingForm>,
+    groups: &State<Vec<Group>>,
+    pool: &State<sqlx::PgPool>
+) -> Result<Redirect, (Status, String)> {
+    let group = findGroup(groups, &form.gruppe)?;
+    let account = group.accounts.get(form.login).ok_or((Status::BadRequest, format!("Ukjent login {}", form.login)))?;
+
+    let dato = NaiveDate::parse_from_str(&form.dato, "%F").map_err(|_| (Status::BadRequest, format!("Ugyldig dato {}", form.dato)))?;
+    let time = |t: &str| NaiveTime::parse_from_str(t, "%H:%M").map_err(|_| (Status::BadRequest, format!("Ugyldig klokkeslett {}", t)));
+    let (start, end) = (dato.and_time(time(&form.fra)?), dato.and_time(time(&form.til)?));
+    if start >= end {
+        return Err((Status::BadRequest, "Økta må slutt etter den starte".to_string()));
+    }
+    let room = group.policy.roomId(form.rom.trim());
+
+    let (client, bookings) = getClientAndBookings(account, &group.policy, pool, Login::Background).await
+        .map_err(|e| (Status::BadGateway, format!("Fikk ikkje kontakt med TP: {}", e)))?;
+    let booking = bookings.iter().find(|b| b.id == form.id)
+        .ok_or((Status::NotFound, format!("Fant ikkje booking {}", form.id)))?;
+
+    client.modifyBooking(booking, &room, &start, &end).await
+        .map_err(|e| (Status::BadGateway, format!("Fikk ikkje endra bookinga: {}", e)))?;
+    claimRoom(pool, &booking.name, &room, &start, &end).await;
+    println!("Admin flytta booking {} for {} te {} {} - {}", booking.id, group.slug, room.rom, start, end);
+    Ok(Redirect::to(uri!(admin)))
+}
+
+#[derive(FromForm)]
+struct DayForm {
+    gruppe: String,
+    day: String,
+}
+
+impl DayForm {
+    fn day(&self) -> Result<NaiveDate, (Status, String)> {
+        NaiveDate::parse_from_str(&self.day, "%F").map_err(|_| (Status::BadRequest, format!("Ugyldig dato {}", self.day)))
+    }
+}
+
+/// Marker en dag som avlyst, og avbestill bookingan vi allerede har den dagen
+#[post("/admin/avlys", data = "<form>")]
+async fn cancelDay(
+    _admin: Admin,
+    form: Form<DayForm>,
+    groups: &State<Vec<Group>>,
+    pool: &State<sqlx::PgPool>
+) -> Result<Redirect, (Status, String)> {
+    let group = findGroup(groups, &form.gruppe)?;
+    sqlx::query("INSERT INTO cancelled_days (gruppe, day) VALUES ($1, $2) ON CONFLICT DO NOTHING")
+        .bind(&group.slug)
+        .bind(form.day()?)
+        .execute(pool.inner())
+        .await
+        .unwrap();
+
+    releaseCancelledBookings(group, pool).await;
+    Ok(Redirect::to(uri!(admin)))
+}
+
+/// Gjør en avlyst dag vanlig igjen, så neste cron-kjøring booke den på nytt
+#[post("/admin/angre", data = "<form>")]
+async fn uncancelDay(
+    _admin: Admin,
+    form: Form<DayForm>,
+    pool: &State<sqlx::PgPool>
+) -> Result<Redirect, (Status, String)> {
+    sqlx::query("DELETE FROM cancelled_days WHERE gruppe = $1 AND day = $2")
+        .bind(&form.gruppe)
+        .bind(form.day()?)
+        .execute(pool.inner())
+        .await
+        .unwrap();
+
+    Ok(Redirect::to(uri!(admin)))
+}
+
+// Hvor mange rader fra booking_attempts vi vise om gangen
+const ATTEMPTS_LIMIT: i64 = 500;
+
+fn parseDay(day: Option<&str>) -> Result<Option<NaiveDate>, (Status, String)> {
+    day.map(|day| NaiveDate::parse_from_str(day, "%F").map_err(|_| (Status::BadRequest, format!("Ugyldig dato {}", day))))
+        .transpose()
+}
+
+/// Alt roboten har gjort, nyeste kjøring først. Med dag=YYYY-MM-DD vises bare det som gjaldt økter den dagen.
+#[get("/admin/logg?<dag>")]
+async fn attempts(
+    _admin: Admin,
+    dag: Option<String>,
+    pool: &State<sqlx::PgPool>
+) -> Result<RawHtml<String>, (Status, String)> {
+    let attempts = historikk::loadAttempts(pool, parseDay(dag.as_deref())?, ATTEMPTS_LIMIT).await;
+
+    // Radan kjem nyeste først, så en ny kjøring starte der run_started endre seg
+    let mut runs: Vec<Vec<&Attempt>> = Vec::new();
+    for attempt in &attempts {
+        match runs.last_mut() {
+            Some(run) if run[0].run_started == attempt.run_started => run.push(attempt),
+            _ => runs.push(vec![attempt]),
+        }
+    }
+
+    Ok(page("Logg", html!{
+        form class="flex flex-row justify-around h-12 pt-3" method="get" action=(uri!(attempts(None::<String>))) {
+            input type="date" name="dag" value=[dag.as_deref()];
+            button type="submit" { "Vis dag" }
+            a href=(uri!(attemptsJson(dag.as_deref()))) { "JSON" }
+        }
+        @for run in &runs {
+            h2 class="text-lg pt-3" { "Kjøring " (run[0].run_started.with_timezone(&chrono_tz::Europe::Oslo).format("%F %H:%M:%S")) }
+            @for attempt in run.iter().rev() {
+                div class="truncate" {
+                    (attempt.gruppe) " " (attempt.kind) " "
+                    @if let Some(start) = attempt.slot_start { (start.format("%a %e. %H:%M")) " " }
+                    (attempt.room.as_deref().unwrap_or("")) " "
+                    @if let Some(login) = attempt.login { "(login " (login) ") " }
+                    (attempt.detail)
+                }
+            }
+        }
+        div class="pb-12" {}
+    }))
+}
+
+/// Samme som loggsida, men som JSON
+#[get("/admin/logg.json?<dag>")]
+async fn attemptsJson(
+    _admin: Admin,
+    dag: Option<&str>,
+    pool: &State<sqlx::PgPool>
+) -> Result<RawJson<String>, (Status, String)> {
+    let attempts = historikk::loadAttempts(pool, parseDay(dag)?, ATTEMPTS_LIMIT).await;
+    Ok(RawJson(serde_json::to_string(&attempts).unwrap()))
+}
@@ -0,0 +1,171 @@
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use maud::html;
+use rocket::{form::{Form, FromForm}, get, http::Status, post, response::{content::RawHtml, Redirect}, routes, uri, Route, State};
+
+use crate::gruppe::{self, Group};
+use crate::page;
+use crate::planner;
+
+/// Hvordan det går med en ekstra booking noen har bedt om
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequestStatus {
+    /// Ikkje booka enda, prøves igjen hver kjøring
+    Venter,
+    /// Booka, room e rommet vi fikk
+    Booka,
+    /// Tida gikk ut før vi fikk booka nåkka
+    Utlopt,
+}
+
+impl std::fmt::Display for RequestStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RequestStatus::Venter => write!(f, "venter"),
+            RequestStatus::Booka => write!(f, "booka"),
+            RequestStatus::Utlopt => write!(f, "utløpt"),
+        }
+    }
+}
+
+/// En ekstra økt noen i gruppa har bedt om via bestillingsskjemaet, utenom dem faste øktene i policyen
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct BookingRequest {
+    pub id: i64,
+    pub start: NaiveDateTime,
+    pub slutt: NaiveDateTime,
+    pub min_kapasitet: i32,
+    pub status: String,
+    pub room: Option<String>,
+    pub detail: String,
+}
+
+pub async fn addRequest(pool: &sqlx::PgPool, gruppe: &str, start: NaiveDateTime, slutt: NaiveDateTime, minKapasitet: i32) -> Result<(), sqlx::Error> {
+    sqlx::query("INSERT INTO booking_requests (gruppe, start, slutt, min_kapasitet, status) VALUES ($1, $2, $3, $4, $5)")
+        .bind(gruppe)
+        .bind(start)
+        .bind(slutt)
+        .bind(minKapasitet)
+        .bind(RequestStatus::Venter.to_string())
+        .execute(pool)
+        .await
+        .map(|_| ())
+}
+
+/// Alle bestillinga te en gruppe som ikkje e ferdig, eldste først sånn at dem som spurte først får rom først
+pub async fn pendingRequests(pool: &sqlx::PgPool, gruppe: &str) -> Vec<BookingRequest> {
+    sqlx::query_as("SELECT id, start, slutt, min_kapasitet, status, room, detail FROM booking_requests WHERE gruppe = $1 AND status = $2 ORDER BY id")
+        .bind(gruppe)
+        .bind(RequestStatus::Venter.to_string())
+        .fetch_all(pool)
+        .await
+        .unwrap()
+}
+
+/// Bestillingan te en gruppe som ikkje har vært enda, for å vis dem på sida
+pub async fn upcomingRequests(pool: &sqlx::PgPool, gruppe: &str, now: &NaiveDateTime) -> Vec<BookingRequest> {
+    sqlx::query_as("SELECT id, start, slutt, min_kapasitet, status, room, detail FROM booking_requests WHERE gruppe = $1 AND slutt > $2 ORDER BY start")
+        .bind(gruppe)
+        .bind(now)
+        .fetch_all(pool)
+        .await
+        .unwrap()
+}
+
+pub async fn updateRequest(pool: &sqlx::PgPool, id: i64, status: RequestStatus, room: Option<&str>, detail: &str) {
+    sqlx::query("UPDATE booking_requests SET status = $2, room = $3, detail = $4 WHERE id = $1")
+        .bind(id)
+        .bind(status.to_string())
+        .bind(room)
+        .bind(detail)
+        .execute(pool)
+        .await
+        .unwrap();
+}
+
+/// Marker bestillinga som starte før now som utløpt, dem rekke vi ikkje lenger
+pub async fn expireRequests(pool: &sqlx::PgPool, gruppe: &str, now: &NaiveDateTime) {
+    sqlx::query("UPDATE booking_requests SET status = $3, detail = 'Fikk ikkje booka før økta starta' WHERE gruppe = $1 AND status = $2 AND start <= $4")
+        .bind(gruppe)
+        .bind(RequestStatus::Venter.to_string())
+        .bind(RequestStatus::Utlopt.to_string())
+        .bind(now)
+        .execute(pool)
+        .await
+        .unwrap();
+}
+
+/// Skjemaet der medlemman i en gruppe kan be om en ekstra økt
+#[get("/g/<slug>/bestill")]
+pub fn requestForm(slug: &str, groups: &State<Vec<Group>>) -> Option<RawHtml<String>> {
+    let group = gruppe::find(groups, slug)?;
+    group.passkode.as_ref()?;
+
+    Some(page("Bestill ekstra økt", html!{
+        form method="post" action=(uri!(addRequestForm(slug))) {
+            div class="flex flex-row justify-around h-12 pt-3" {
+                span { "Dato" }
+                input type="date" name="dato" min=(Utc::now().date_naive().format("%F")) required;
+            }
+            div class="flex flex-row justify-around h-12 pt-3" {
+                span { "Fra" }
+                input type="time" name="fra" required;
+                span { "Til" }
+                input type="time" name="til" required;
+            }
+            div class="flex flex-row justify-around h-12 pt-3" {
+                span { "Minst antall plassa" }
+                input type="number" name="kapasitet" min=(group.policy.minKapasitet) value=(group.policy.minKapasitet) required;
+            }
+            div class="flex flex-row justify-around h-12 pt-3" {
+                span { "Passkode" }
+                input type="password" name="passkode" required;
+            }
+            div class="pt-3 pb-12" { button type="submit" { "Bestill" } }
+        }
+    }))
+}
+
+#[derive(FromForm)]
+struct RequestForm {
+    dato: String,
+    fra: String,
+    til: String,
+    kapasitet: u16,
+    passkode: String,
+}
+
+/// Legg bestillinga i køen. Den bookes neste gang bookingkjøringa går og dagen e innafor horisonten te TP.
+#[post("/g/<slug>/bestill", data = "<form>")]
+async fn addRequestForm(
+    slug: &str,
+    form: Form<RequestForm>,
+    groups: &State<Vec<Group>>,
+    pool: &State<sqlx::PgPool>
+) -> Result<Redirect, (Status, String)> {
+    let group = gruppe::find(groups, slug).ok_or((Status::NotFound, format!("Ukjent gruppe {}", slug)))?;
+    if group.passkode.as_deref() != Some(form.passkode.as_str()) {
+        return Err((Status::Forbidden, "Feil passkode".to_string()));
+    }
+
+    let dato = NaiveDate::parse_from_str(&form.dato, "%F").map_err(|_| (Status::BadRequest, format!("Ugyldig dato {}", form.dato)))?;
+    let time = |t: &str| NaiveTime::parse_from_str(t, "%H:%M").map_err(|_| (Status::BadRequest, format!("Ugyldig klokkeslett {}", t)));
+    let (start, slutt) = (dato.and_time(time(&form.fra)?), dato.and_time(time(&form.til)?));
+    if start >= slutt {
+        return Err((Status::BadRequest, "Økta må slutt etter den starte".to_string()));
+    }
+    // Klokkeslett som ikkje finnes fordi klokka stilles fram kan ikkje bookes i TP heller
+    if planner::tryOsloTilUtc(&start).is_none() || planner::tryOsloTilUtc(&slutt).is_none() {
+        return Err((Status::BadRequest, "Det klokkeslettet finnes ikkje den dagen, for da stilles klokka fram".to_string()));
+    }
+    if start <= planner::osloNaa() {
+        return Err((Status::BadRequest, "Økta må vær fram i tid".to_string()));
+    }
+
+    addRequest(pool, &group.slug, start, slutt, form.kapasitet.max(group.policy.minKapasitet).into()).await.unwrap();
+    println!("Ny bestilling for {}: {} - {}", group.slug, start, slutt);
+    Ok(Redirect::to(uri!(crate::index(slug))))
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![requestForm, addRequestForm]
+}
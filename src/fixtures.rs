@@ -0,0 +1,435 @@
+use std::{collections::HashMap, path::PathBuf, sync::{Mutex, OnceLock}};
+
+use chrono::{DateTime, NaiveDate, Utc};
+use reqwest::{cookie::{CookieStore, Jar}, header::HeaderValue, RequestBuilder, Response, ResponseBuilderExt, Url};
+use serde::{Deserialize, Serialize};
+
+// Alt vi gjør mot TP, feide, MazeMap og Notion går gjennom sendRecorded, sånn at det kan tas opp og spilles av igjen uten nett.
+// HTTP_FIXTURES=record kjøre som vanlig, men lagre hvert svar i HTTP_FIXTURES_DIR (fixtures/opptak som default, den ignoreres av git).
+// HTTP_FIXTURES=replay sende ingenting, og svare med det som ble tatt opp. Uten HTTP_FIXTURES går alt rett ut som før.
+//
+// Svaran lagres per request, med metode, url og en hash av bodyen som nøkkel. Kommer samme request flere gang
+// får man svaran i samme rekkefølge som dem ble tatt opp, og det siste om igjen når dem e brukt opp.
+// Datoan i urlen og bodyen lagres relativt te dagen i dag, sånn at timeplanen fra fromdate=i dag matche også i morra.
+// Opptaket lagre også når det starta i klokka.json, og når det spilles av står fixtures::now() stille der,
+// sånn at planen og det som regnes som gammelt bli det samme som da det ble tatt opp.
+// Testan spille av dem håndskrevne opptakan i fixtures/test på samme måte, se replayForTest.
+//
+// Request bodyen lagres ikkje, for den kan inneholde passord, og passordet fjernes før bodyen hashes.
+// Query stringen havne bare i hashen, og adressa som e hemmelige i seg sjøl, som ICAL_LINKS, sendes med
+// sendRecordedSecret sånn at bare hosten lagres. Men svaran fra feide og cookiesa inneholde sessions,
+// så opptak av ekte trafikk hører ikkje heime i git.
+
+#[derive(Debug, Clone)]
+enum Mode {
+    Live,
+    Record(PathBuf),
+    /// Med klokka fra da opptaket starta, om den e lagra
+    Replay(PathBuf, Option<DateTime<Utc>>),
+}
+
+#[cfg(test)]
+thread_local! {
+    /// Testan spille av sine egne opptak, uten å røre HTTP_FIXTURES
+    static TEST_MODE: std::cell::RefCell<Option<Mode>> = const { std::cell::RefCell::new(None) };
+    /// Requestsa som e spilt av i testen, sånn at testen kan sjekk hva som ble sendt
+    static TEST_REQUESTS: std::cell::RefCell<Vec<String>> = const { std::cell::RefCell::new(Vec::new()) };
+}
+
+/// Spill av opptakan i dir for resten av testen, med klokka stilt te da dem ble tatt opp
+#[cfg(test)]
+pub fn replayForTest(dir: &str) {
+    let dir = PathBuf::from(dir);
+    let clock = loadClock(&dir);
+    assert!(clock.is_some(), "Mangle {} i {}", CLOCK_FILE, dir.display());
+    TEST_MODE.with(|mode| *mode.borrow_mut() = Some(Mode::Replay(dir, clock)));
+    TEST_REQUESTS.with(|requests| requests.borrow_mut().clear());
+}
+
+/// Metode og adresse, sånn som i fixture filan, te alle requestsa som e spilt av siden replayForTest
+#[cfg(test)]
+pub fn replayedRequests() -> Vec<String> {
+    TEST_REQUESTS.with(|requests| requests.borrow().clone())
+}
+
+fn mode() -> Mode {
+    #[cfg(test)]
+    if let Some(mode) = TEST_MODE.with(|mode| mode.borrow().clone()) {
+        return mode;
+    }
+    static MODE: OnceLock<Mode> = OnceLock::new();
+    MODE.get_or_init(|| {
+        let dir = std::env::var("HTTP_FIXTURES_DIR").map(PathBuf::from)
+            .unwrap_or_else(|_| PathBuf::from(rocket::fs::relative!("fixtures/opptak")));
+        match std::env::var("HTTP_FIXTURES").as_deref() {
+            Ok("record") => {
+                saveClock(&dir);
+                Mode::Record(dir)
+            },
+            Ok("replay") => {
+                let clock = loadClock(&dir);
+                Mode::Replay(dir, clock)
+            },
+            Ok(other) if !other.is_empty() => panic!("Ukjent HTTP_FIXTURES {}, bruk record eller replay", other),
+            _ => Mode::Live,
+        }
+    }).clone()
+}
+
+/// Når opptaket starta. Tar man opp flere gang i samme mappe gjelde det første.
+const CLOCK_FILE: &str = "klokka.json";
+
+fn loadClock(dir: &std::path::Path) -> Option<DateTime<Utc>> {
+    let clock = std::fs::read_to_string(dir.join(CLOCK_FILE)).ok()?;
+    Some(serde_json::from_str(&clock).unwrap_or_else(|e| panic!("Ugyldig {} i {}: {}", CLOCK_FILE, dir.display(), e)))
+}
+
+fn saveClock(dir: &std::path::Path) {
+    if loadClock(dir).is_none() {
+        std::fs::create_dir_all(dir).unwrap();
+        std::fs::write(dir.join(CLOCK_FILE), serde_json::to_string(&Utc::now()).unwrap()).unwrap();
+    }
+}
+
+/// Klokka alt som ska kunne spilles av bruke i stedet for Utc::now(). Når et opptak spilles av står den stille
+/// på da opptaket starta, så datoan i requestsa og det som regnes som gammelt bli det samme som da.
+pub fn now() -> DateTime<Utc> {
+    match mode() {
+        Mode::Replay(_, Some(clock)) => clock,
+        _ => Utc::now(),
+    }
+}
+
+fn today() -> NaiveDate {
+    now().date_naive()
+}
+
+/// Bytt ut alle datoan i teksten med hvor mange dager dem e fra today, så 2027-01-12 bli {I_DAG+1} om today e 2027-01-11
+fn relativeDates(text: &str, today: NaiveDate) -> String {
+    let mut result = String::new();
+    let mut rest = text;
+    while !rest.is_empty() {
+        let date = rest.get(..10).filter(|d| d.as_bytes()[4] == b'-').and_then(|d| NaiveDate::parse_from_str(d, "%F").ok());
+        match date {
+            Some(date) => {
+                result.push_str(&format!("{{I_DAG{:+}}}", (date - today).num_days()));
+                rest = &rest[10..];
+            },
+            None => {
+                let c = rest.chars().next().unwrap();
+                result.push(c);
+                rest = &rest[c.len_utf8()..];
+            },
+        }
+    }
+    result
+}
+
+/// Motsatt av relativeDates, så {I_DAG+1} bli dagen etter today
+fn absoluteDates(text: &str, today: NaiveDate) -> String {
+    let mut result = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("{I_DAG") {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let days = rest.find('}').and_then(|end| Some((rest[6..end].parse::<i64>().ok()?, end)));
+        match days.and_then(|(days, end)| Some((today.checked_add_signed(chrono::Duration::days(days))?, end))) {
+            Some((date, end)) => {
+                result.push_str(&date.format("%F").to_string());
+                rest = &rest[end + 1..];
+            },
+            None => {
+                result.push('{');
+                rest = &rest[1..];
+            },
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+/// Et svar sånn det ligg i en fixture fil. Datoan i url og body e relative, som i requesten.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RecordedResponse {
+    status: u16,
+    /// Adressen vi endte opp på etter redirects, den bruke innlogginga
+    url: String,
+    headers: Vec<(String, String)>,
+    body: String,
+}
+
+impl RecordedResponse {
+    async fn read(response: Response) -> Result<RecordedResponse, reqwest::Error> {
+        let status = response.status().as_u16();
+        let url = response.url().to_string();
+        let headers = response.headers().iter()
+            .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+            .collect();
+        let body = response.text().await?;
+        Ok(RecordedResponse { status, url, headers, body })
+    }
+
+    /// Med datoan relativt te today, sånn det lagres
+    fn relative(self, today: NaiveDate) -> RecordedResponse {
+        RecordedResponse { url: relativeDates(&self.url, today), body: relativeDates(&self.body, today), ..self }
+    }
+
+    fn toResponse(&self, today: NaiveDate) -> Response {
+        let mut builder = http::Response::builder()
+            .status(self.status)
+            .url(Url::parse(&absoluteDates(&self.url, today)).unwrap());
+        // Bodyen e allerede pakka ut, så den må ikkje pakkes ut på nytt
+        for (name, value) in self.headers.iter().filter(|(name, _)| name != "content-encoding" && name != "content-length") {
+            builder = builder.header(name, value);
+        }
+        Response::from(builder.body(absoluteDates(&self.body, today)).unwrap())
+    }
+}
+
+/// Alle opptakan av én request, i rekkefølge
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Fixture {
+    method: String,
+    url: String,
+    responses: Vec<RecordedResponse>,
+    /// Cookiesa i jaren etter requesten, sånn at innlogginga kan spilles av. Se cookies.
+    #[serde(default)]
+    cookies: Option<String>,
+}
+
+/// FNV-1a, fordi std sin hasher ikkje lova samme hash mellom Rust versjona
+fn hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3))
+}
+
+/// Bodyen med passordet tomt, sånn at ikkje engang en hash av passordet havne i filnavnet
+fn redacted(body: &[u8]) -> Vec<u8> {
+    let Ok(body) = std::str::from_utf8(body) else { return body.to_vec() };
+    body.split('&')
+        .map(|param| if param.starts_with("password=") { "password=" } else { param })
+        .collect::<Vec<_>>()
+        .join("&")
+        .into_bytes()
+}
+
+/// Adressen sånn den lagres i fixture fila: uten query string, og for hemmelige adressa bare hosten
+fn storedUrl(url: &str, secret: bool) -> String {
+    let Ok(mut url) = Url::parse(url) else { return String::new() };
+    url.set_query(None);
+    url.set_fragment(None);
+    if secret {
+        url.set_path("");
+    }
+    url.to_string()
+}
+
+/// Filnavnet te en request. Starten e lesbar, og hashen av heile requesten skille mellom requests med samme start.
+fn fixturePath(dir: &std::path::Path, method: &str, url: &str, body: &[u8], secret: bool) -> PathBuf {
+    let today = today();
+    let url = &relativeDates(url, today);
+    let body = relativeDates(&String::from_utf8_lossy(&redacted(body)), today);
+    let readable: String = format!("{}_{}", method, storedUrl(url, secret).trim_start_matches("https://").trim_start_matches("http://"))
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .take(80)
+        .collect();
+    let key = hash(&[method.as_bytes(), url.as_bytes(), body.as_bytes()].concat());
+    dir.join(format!("{}_{:016x}.json", readable, key))
+}
+
+fn loadFixture(path: &std::path::Path) -> Option<Fixture> {
+    let fixture = std::fs::read_to_string(path).ok()?;
+    Some(serde_json::from_str(&fixture).unwrap_or_else(|e| panic!("Ugyldig fixture {}: {}", path.display(), e)))
+}
+
+fn saveFixture(path: &std::path::Path, fixture: &Fixture) {
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(path, serde_json::to_string_pretty(fixture).unwrap()).unwrap();
+}
+
+/// Hvor mange svar vi har spilt av per fixture fil, og en lås sånn at to opptak ikkje skriv samme fil samtidig
+fn replayed() -> &'static Mutex<HashMap<PathBuf, usize>> {
+    static REPLAYED: OnceLock<Mutex<HashMap<PathBuf, usize>>> = OnceLock::new();
+    REPLAYED.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// url e adressen sånn den lagres, så en hemmelig adresse havne ikkje i feilmeldinga heller
+fn replay(path: &std::path::Path, method: &str, url: &str) -> Response {
+    let fixture = loadFixture(path)
+        .unwrap_or_else(|| panic!("Mangle fixture for {} {} ({}), ta den opp med HTTP_FIXTURES=record", method, url, path.display()));
+    let mut replayed = replayed().lock().unwrap();
+    let count = replayed.entry(path.to_path_buf()).or_insert(0);
+    let response = &fixture.responses[(*count).min(fixture.responses.len() - 1)];
+    *count += 1;
+    #[cfg(test)]
+    TEST_REQUESTS.with(|requests| requests.borrow_mut().push(format!("{} {}", method, url)));
+    response.toResponse(today())
+}
+
+fn record(path: &std::path::Path, method: &str, url: &str, response: RecordedResponse) {
+    let _lock = replayed().lock().unwrap();
+    let mut fixture = loadFixture(path).unwrap_or_else(|| Fixture { method: method.to_string(), url: url.to_string(), ..Default::default() });
+    fixture.responses.push(response);
+    saveFixture(path, &fixture);
+}
+
+/// Send en request, eller ta den opp eller spill den av om HTTP_FIXTURES e satt
+pub trait RecordReplay {
+    async fn sendRecorded(self) -> Result<Response, reqwest::Error>;
+    /// Som sendRecorded, men adressen e hemmelig, så den havne ikkje i fixture fila
+    async fn sendRecordedSecret(self) -> Result<Response, reqwest::Error>;
+}
+
+impl RecordReplay for RequestBuilder {
+    async fn sendRecorded(self) -> Result<Response, reqwest::Error> {
+        send(self, false).await
+    }
+
+    async fn sendRecordedSecret(self) -> Result<Response, reqwest::Error> {
+        send(self, true).await
+    }
+}
+
+async fn send(builder: RequestBuilder, secret: bool) -> Result<Response, reqwest::Error> {
+    let mode = mode();
+    let dir = match &mode {
+        Mode::Live => return builder.send().await,
+        Mode::Record(dir) | Mode::Replay(dir, _) => dir,
+    };
+
+    let (client, request) = builder.build_split();
+    let request = request?;
+    let method = request.method().to_string();
+    let url = request.url().to_string();
+    let body = request.body().and_then(|b| b.as_bytes()).unwrap_or_default();
+    let path = fixturePath(dir, &method, &url, body, secret);
+
+    if let Mode::Replay(..) = mode {
+        return Ok(replay(&path, &method, &storedUrl(&url, secret)));
+    }
+    let mut response = RecordedResponse::read(client.execute(request).await?).await?;
+    // Innlogginga treng query stringen i adressen vi havna på, men den hemmelige adressen trengs ikkje
+    if secret {
+        response.url = storedUrl(&response.url, true);
+    }
+    let today = today();
+    record(&path, &method, &storedUrl(&relativeDates(&url, today), secret), response.clone().relative(today));
+    Ok(response.toResponse(today))
+}
+
+/// Cookiesa i jaren for en url. Når innlogginga spilles av går ingen redirects gjennom jaren,
+/// så da bruke vi cookiesa som va der da den ble tatt opp.
+pub fn cookies(jar: &Jar, url: &Url) -> Option<HeaderValue> {
+    let mode = mode();
+    let path = match &mode {
+        Mode::Live => return jar.cookies(url),
+        Mode::Record(dir) | Mode::Replay(dir, _) => fixturePath(dir, "COOKIES", url.as_str(), &[], false),
+    };
+
+    if let Mode::Replay(..) = mode {
+        return loadFixture(&path)?.cookies.and_then(|c| HeaderValue::from_str(&c).ok());
+    }
+    let cookies = jar.cookies(url);
+    let fixture = Fixture {
+        method: "COOKIES".to_string(),
+        url: storedUrl(url.as_str(), false),
+        responses: Vec::new(),
+        cookies: cookies.as_ref().and_then(|c| c.to_str().ok()).map(String::from),
+    };
+    saveFixture(&path, &fixture);
+    cookies
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+
+    use chrono::Days;
+    use reqwest::Client;
+
+    use super::*;
+    use crate::planner::{self, ExistingBooking, Quota};
+    use crate::policy::{BookingPolicy, TEST_POLICY};
+    use crate::tp::ClientMethods;
+
+    #[test]
+    fn datoanBlirRelative() {
+        let today = NaiveDate::from_ymd_opt(2027, 1, 11).unwrap();
+        assert_eq!(relativeDates("room.php?id=250A&fromdate=2027-01-11&todate=2027-01-25", today),
+            "room.php?id=250A&fromdate={I_DAG+0}&todate={I_DAG+14}");
+        assert_eq!(relativeDates("2027-01-10", today), "{I_DAG-1}");
+        assert_eq!(absoluteDates("fromdate={I_DAG+0}&todate={I_DAG+14} {I_DAG-1}", today), "fromdate=2027-01-11&todate=2027-01-25 2027-01-10");
+        assert_eq!(absoluteDates("{I_DAG}{ikkje en dato}", today), "{I_DAG}{ikkje en dato}");
+    }
+
+    #[test]
+    fn passordetHashesIkkje() {
+        let dir = std::path::Path::new("fixtures");
+        assert_eq!(fixturePath(dir, "POST", "https://feide", b"has_js=0&feidename=a&password=hemmelig", false),
+            fixturePath(dir, "POST", "https://feide", b"has_js=0&feidename=a&password=annet", false));
+    }
+
+    #[test]
+    fn hemmeligeAdresserLagresIkkje() {
+        let dir = std::path::Path::new("fixtures");
+        let ical = "https://kalender.example/ical/hemmelig123/basic.ics?token=abc";
+        let path = fixturePath(dir, "GET", ical, &[], true).to_string_lossy().to_string();
+        assert!(path.starts_with("fixtures/GET_kalender_example__"), "{}", path);
+        assert!(!path.contains("hemmelig") && !path.contains("abc"));
+        assert_eq!(storedUrl(ical, true), "https://kalender.example/");
+
+        // Query stringen e bare med i hashen
+        let room = "https://tp.educloud.no/ntnu/ws/1.4/room.php?id=250A";
+        assert_eq!(storedUrl(room, false), "https://tp.educloud.no/ntnu/ws/1.4/room.php");
+        assert_ne!(fixturePath(dir, "GET", room, &[], false), fixturePath(dir, "GET", &room.replace("250A", "250B"), &[], false));
+    }
+
+    /// Samme vei som bookRooms, fra bookingan og timeplanan i TP te planen og reservasjonan, spilt av fra fixtures/test.
+    /// Opptaket starta mandag 2027-01-11 07:00, der har A en forelesning onsdag, og vi har B tirsdag som kan byttes te A.
+    #[tokio::test]
+    async fn planOgBookFraOpptak() {
+        replayForTest(rocket::fs::relative!("fixtures/test"));
+        let today = today();
+        assert_eq!(today, NaiveDate::from_ymd_opt(2027, 1, 11).unwrap());
+        let policy = BookingPolicy::parse(TEST_POLICY).unwrap();
+        let client = Client::new();
+
+        let bookings = client.getBookings(&policy.institusjon).await.unwrap();
+        let existing: Vec<ExistingBooking> = bookings.iter()
+            .map(|booking| ExistingBooking {
+                id: booking.id.clone(),
+                clientIndex: 0,
+                start: booking.booked,
+                end: booking.end().unwrap(),
+                room: booking.room().to_string(),
+            })
+            .collect();
+
+        let toDate = today.checked_add_days(Days::new(policy.horisontDager.into())).unwrap();
+        let mut schedules = HashMap::new();
+        for room in &policy.rom {
+            let schedule = client.getScheduleForRoom(&policy.roomId(&room.navn), today, toDate).await.unwrap();
+            schedules.insert(room.navn.clone(), schedule.events);
+        }
+
+        let quotas = [Quota { used: bookings.len() as u8, max: policy.bookingsPerLogin }];
+        let plan = planner::planBookings(&policy, &existing, &quotas, &schedules, &HashSet::new(), &[], &planner::osloNaa());
+
+        assert_eq!(plan.upgrades.len(), 1);
+        let upgrade = &plan.upgrades[0];
+        assert_eq!((upgrade.booking.room.as_str(), upgrade.room.as_str()), ("B", "A"));
+        let rooms: Vec<_> = plan.slots.iter().map(|slot| (slot.start.format("%F").to_string(), slot.room.clone().unwrap())).collect();
+        assert_eq!(rooms, [("2027-01-13".to_string(), "B".to_string()), ("2027-01-14".to_string(), "A".to_string())]);
+
+        let response = client.bookRoom(&policy.navn, &policy.roomId(&upgrade.room), &upgrade.booking.start, &upgrade.booking.end).await.unwrap();
+        assert_eq!(response.id.as_deref(), Some("101"));
+        client.cancelBooking(&policy.institusjon, &upgrade.booking.id).await.unwrap();
+
+        let mut ids = Vec::new();
+        for slot in &plan.slots {
+            let response = client.bookRoom(&policy.navn, &policy.roomId(slot.room.as_ref().unwrap()), &slot.start, &slot.end).await.unwrap();
+            ids.extend(response.id);
+        }
+        assert_eq!(ids, ["102", "103"]);
+    }
+}
@@ -0,0 +1,75 @@
+use std::collections::HashSet;
+
+use serde::Deserialize;
+use shuttle_runtime::SecretStore;
+
+use crate::konto::Account;
+use crate::policy::BookingPolicy;
+
+const GRUPPER_FIL: &str = rocket::fs::relative!("grupper.toml");
+
+/// En gruppe som bruke tjenesten, med sin egen policy, sine egne feide brukera og sin egen side på /g/<slug>.
+/// passkode e koden te bestillingsskjemaet, uten den e skjemaet skrudd av.
+#[derive(Clone)]
+pub struct Group {
+    pub slug: String,
+    pub policy: BookingPolicy,
+    pub accounts: Vec<Account>,
+    pub passkode: Option<String>,
+}
+
+/// Sånn gruppene står i grupper.toml
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct GroupConfig {
+    slug: String,
+    policy: String,
+    secrets: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct GroupsFile {
+    gruppe: Vec<GroupConfig>,
+}
+
+impl Group {
+    /// Les inn alle gruppene fra grupper.toml, med policy og kontoa te hver
+    pub async fn loadAll(pool: &sqlx::PgPool, secretStore: &SecretStore) -> Result<Vec<Group>, String> {
+        let groupsStr = std::fs::read_to_string(GRUPPER_FIL)
+            .map_err(|e| format!("Fikk ikkje lest {}: {}", GRUPPER_FIL, e))?;
+        let groupsFile: GroupsFile = toml::from_str(&groupsStr).map_err(|e| e.to_string())?;
+
+        let mut groups = Vec::new();
+        for config in groupsFile.gruppe {
+            if config.slug.is_empty() || !config.slug.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-') {
+                return Err(format!("Ugyldig slug {}, bruk bare a-z, 0-9 og -", config.slug));
+            }
+            let policy = BookingPolicy::load(pool, &config.slug, &config.policy).await
+                .map_err(|e| format!("{}: {}", config.slug, e))?;
+            let accounts = Account::loadAll(secretStore, &config.secrets, policy.bookingsPerLogin)
+                .map_err(|e| format!("{}: {}", config.slug, e))?;
+            let passkode = secretStore.get(&format!("{}_PASSKODE", config.secrets)).filter(|p| !p.is_empty());
+            groups.push(Group { slug: config.slug, policy, accounts, passkode });
+        }
+
+        if groups.is_empty() {
+            return Err("Det må vær minst én gruppe".to_string());
+        }
+        let mut slugs = HashSet::new();
+        if let Some(group) = groups.iter().find(|g| !slugs.insert(&g.slug)) {
+            return Err(format!("{} e med flere gang i grupper.toml", group.slug));
+        }
+        // Vi kjenne igjen våre egne bookinga på navnet, så to grupper med samme navn ville trudd dem eide hverandres rom
+        let mut navn = HashSet::new();
+        if let Some(group) = groups.iter().find(|g| !navn.insert(&g.policy.navn)) {
+            return Err(format!("{} bruke samme booking navn som en annen gruppe", group.slug));
+        }
+        Ok(groups)
+    }
+}
+
+/// Gruppa med en gitt slug
+pub fn find<'a>(groups: &'a [Group], slug: &str) -> Option<&'a Group> {
+    groups.iter().find(|g| g.slug == slug)
+}
@@ -0,0 +1,83 @@
+use chrono::{Datelike, NaiveDate};
+
+/// Første påskedag for et gitt år, regna ut med den anonyme gregorianske algoritmen (Meeus/Jones/Butcher)
+pub fn easterSunday(year: i32) -> NaiveDate {
+    let a = year % 19;
+    let b = year / 100;
+    let c = year % 100;
+    let d = b / 4;
+    let e = b % 4;
+    let f = (b + 8) / 25;
+    let g = (b - f + 1) / 3;
+    let h = (19 * a + b - d - g + 15) % 30;
+    let i = c / 4;
+    let k = c % 4;
+    let l = (32 + 2 * e + 2 * i - h - k) % 7;
+    let m = (a + 11 * h + 22 * l) / 451;
+    let month = (h + l - 7 * m + 114) / 31;
+    let day = (h + l - 7 * m + 114) % 31 + 1;
+    NaiveDate::from_ymd_opt(year, month as u32, day as u32).unwrap()
+}
+
+/// Alle norske offentlige helligdager for et år, både dem faste og dem som flytte seg med påsken
+pub fn publicHolidays(year: i32) -> Vec<(NaiveDate, &'static str)> {
+    let fixed = |month: u32, day: u32| NaiveDate::from_ymd_opt(year, month, day).unwrap();
+    let easter = easterSunday(year);
+    let fromEaster = |days: i64| easter.checked_add_signed(chrono::Duration::days(days)).unwrap();
+
+    vec![
+        (fixed(1, 1), "Første nyttårsdag"),
+        (fromEaster(-3), "Skjærtorsdag"),
+        (fromEaster(-2), "Langfredag"),
+        (easter, "Første påskedag"),
+        (fromEaster(1), "Andre påskedag"),
+        (fixed(5, 1), "Arbeidernes dag"),
+        (fixed(5, 17), "Grunnlovsdag"),
+        (fromEaster(39), "Kristi himmelfartsdag"),
+        (fromEaster(49), "Første pinsedag"),
+        (fromEaster(50), "Andre pinsedag"),
+        (fixed(12, 25), "Første juledag"),
+        (fixed(12, 26), "Andre juledag"),
+    ]
+}
+
+/// Navnet på helligdagen om datoen e en, ellers None
+pub fn holidayName(date: &NaiveDate) -> Option<&'static str> {
+    publicHolidays(date.year()).into_iter()
+        .find(|(holiday, _)| holiday == date)
+        .map(|(_, name)| name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(date: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(date, "%F").unwrap()
+    }
+
+    fn holidayDate(year: i32, name: &str) -> NaiveDate {
+        publicHolidays(year).into_iter().find(|(_, n)| *n == name).unwrap().0
+    }
+
+    #[test]
+    fn påskedagan() {
+        for (year, easter) in [(2024, "2024-03-31"), (2025, "2025-04-20"), (2026, "2026-04-05"), (2027, "2027-03-28")] {
+            assert_eq!(easterSunday(year), date(easter));
+        }
+    }
+
+    #[test]
+    fn himmelfartOgPinse() {
+        for year in 2024..=2027 {
+            let easter = easterSunday(year);
+            let days = |name: &str| (holidayDate(year, name) - easter).num_days();
+            assert_eq!(days("Kristi himmelfartsdag"), 39);
+            assert_eq!(days("Første pinsedag"), 49);
+            assert_eq!(days("Andre pinsedag"), 50);
+        }
+        assert_eq!(holidayName(&date("2025-05-29")), Some("Kristi himmelfartsdag"));
+        assert_eq!(holidayName(&date("2025-06-09")), Some("Andre pinsedag"));
+        assert_eq!(holidayName(&date("2025-06-10")), None);
+    }
+}
@@ -0,0 +1,108 @@
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use serde::Serialize;
+
+/// Hva slags ting som skjedde i en bookingkjøring
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttemptKind {
+    /// En kjøring starta
+    Run,
+    /// Planleggern så på en økt, room e rommet den valgte om det va nåkka ledig
+    Slot,
+    /// Et rom ble ikkje valgt for en økt, detail e grunnen
+    Rejected,
+    /// TP ga oss rommet
+    Booked,
+    /// TP ville ikkje gi oss rommet, detail e feilen
+    Failed,
+    /// En booking ble bytta te et bedre rom, eller vi prøvde
+    Upgraded,
+    /// Kvoten va brukt opp
+    Quota,
+}
+
+impl std::fmt::Display for AttemptKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AttemptKind::Run => write!(f, "run"),
+            AttemptKind::Slot => write!(f, "slot"),
+            AttemptKind::Rejected => write!(f, "rejected"),
+            AttemptKind::Booked => write!(f, "booked"),
+            AttemptKind::Failed => write!(f, "failed"),
+            AttemptKind::Upgraded => write!(f, "upgraded"),
+            AttemptKind::Quota => write!(f, "quota"),
+        }
+    }
+}
+
+/// Loggen for én bookingkjøring. Alt som logges får samme runStarted, sånn at vi kan se kjøringa samla etterpå.
+/// Alt skrives ut med println også, som før.
+pub struct BookingLog<'a> {
+    pool: &'a sqlx::PgPool,
+    gruppe: &'a str,
+    runStarted: DateTime<Utc>,
+}
+
+impl<'a> BookingLog<'a> {
+    pub async fn start(pool: &'a sqlx::PgPool, gruppe: &'a str) -> BookingLog<'a> {
+        let log = BookingLog { pool, gruppe, runStarted: Utc::now() };
+        log.log(AttemptKind::Run, None, None, None, "Starta bookingkjøring").await;
+        log
+    }
+
+    /// Skriv en rad te booking_attempts. slot e start og slutt på økta det gjelde, login e indexen te kontoen.
+    /// Om databasen feile skrives det bare ut, loggen ska aldri stopp selve bookinga.
+    pub async fn log(
+        &self,
+        kind: AttemptKind,
+        slot: Option<(&NaiveDateTime, &NaiveDateTime)>,
+        room: Option<&str>,
+        login: Option<usize>,
+        detail: impl std::fmt::Display
+    ) {
+        let detail = detail.to_string();
+        println!("{} {} {} {} {}", self.gruppe, kind, slot.map(|(start, _)| start.to_string()).unwrap_or_default(), room.unwrap_or(""), detail);
+
+        let result = sqlx::query("INSERT INTO booking_attempts (gruppe, run_started, kind, slot_start, slot_end, room, login, detail)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)")
+            .bind(self.gruppe)
+            .bind(self.runStarted)
+            .bind(kind.to_string())
+            .bind(slot.map(|(start, _)| *start))
+            .bind(slot.map(|(_, end)| *end))
+            .bind(room)
+            .bind(login.and_then(|l| i32::try_from(l).ok()))
+            .bind(&detail)
+            .execute(self.pool)
+            .await;
+        if let Err(e) = result {
+            println!("Fikk ikkje lagra i booking_attempts: {}", e);
+        }
+    }
+}
+
+/// En rad fra booking_attempts
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct Attempt {
+    pub id: i64,
+    pub gruppe: String,
+    pub run_started: DateTime<Utc>,
+    pub logged_at: DateTime<Utc>,
+    pub kind: String,
+    pub slot_start: Option<NaiveDateTime>,
+    pub slot_end: Option<NaiveDateTime>,
+    pub room: Option<String>,
+    pub login: Option<i32>,
+    pub detail: String,
+}
+
+/// Skaffe dem siste limit radan, nyeste først. Med day får man bare radan for økter den dagen.
+pub async fn loadAttempts(pool: &sqlx::PgPool, day: Option<NaiveDate>, limit: i64) -> Vec<Attempt> {
+    sqlx::query_as("SELECT id, gruppe, run_started, logged_at, kind, slot_start, slot_end, room, login, detail FROM booking_attempts
+        WHERE $1::date IS NULL OR slot_start::date = $1
+        ORDER BY id DESC LIMIT $2")
+        .bind(day)
+        .bind(limit)
+        .fetch_all(pool)
+        .await
+        .unwrap()
+}
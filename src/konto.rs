@@ -0,0 +1,80 @@
+use shuttle_runtime::SecretStore;
+
+/// En feide bruker vi booke med. index e plassen i lista te gruppa,
+/// og kvote e hvor mange bookinga TP lar brukeren ha samtidig.
+#[derive(Clone)]
+pub struct Account {
+    pub index: usize,
+    pub brukernavn: String,
+    pub passord: String,
+    pub kvote: u8,
+}
+
+impl Account {
+    /// Les inn kontoan fra secretsa. <prefix>_BRUKERNAVN og <prefix>_PASSORD e kommaseparerte lister med like mange element,
+    /// og <prefix>_KVOTE kan gi hver konto sin egen kvote. Uten den får alle defaultKvote.
+    pub fn loadAll(secretStore: &SecretStore, prefix: &str, defaultKvote: u8) -> Result<Vec<Account>, String> {
+        let brukernavnKey = format!("{}_BRUKERNAVN", prefix);
+        let passordKey = format!("{}_PASSORD", prefix);
+        let kvoteKey = format!("{}_KVOTE", prefix);
+        let list = |key: &str| secretStore.get(key).map(|v| v.split(',').map(String::from).collect::<Vec<String>>());
+
+        let brukernavn: Vec<String> = list(&brukernavnKey).ok_or(format!("Sett {}", brukernavnKey))?
+            .into_iter().map(|b| b.trim().to_string()).collect();
+        let passord = list(&passordKey).ok_or(format!("Sett {}", passordKey))?;
+
+        if brukernavn.iter().any(|b| b.is_empty()) {
+            return Err(format!("{} har et tomt brukernavn", brukernavnKey));
+        }
+        if brukernavn.len() != passord.len() {
+            return Err(format!("{} har {} brukera, men {} har {} passord", brukernavnKey, brukernavn.len(), passordKey, passord.len()));
+        }
+
+        let kvoter: Vec<u8> = match list(&kvoteKey) {
+            Some(kvoter) => kvoter.iter()
+                .map(|k| k.trim().parse().map_err(|_| format!("Ugyldig kvote {} i {}", k, kvoteKey)))
+                .collect::<Result<_, _>>()?,
+            None => vec![defaultKvote; brukernavn.len()],
+        };
+        if kvoter.len() != brukernavn.len() {
+            return Err(format!("{} har {} kvoter, men det e {} brukera", kvoteKey, kvoter.len(), brukernavn.len()));
+        }
+
+        Ok(brukernavn.into_iter().zip(passord).zip(kvoter).enumerate()
+            .map(|(index, ((brukernavn, passord), kvote))| Account { index, brukernavn, passord, kvote })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secrets(pairs: &[(&str, &str)]) -> SecretStore {
+        let map: serde_json::Map<String, serde_json::Value> = pairs.iter().map(|(k, v)| (k.to_string(), v.to_string().into())).collect();
+        serde_json::from_value(map.into()).unwrap()
+    }
+
+    #[test]
+    fn kontoanLesesFraSecretsa() {
+        let accounts = Account::loadAll(&secrets(&[("FEIDE_BRUKERNAVN", "ola, kari"), ("FEIDE_PASSORD", "a,b"), ("FEIDE_KVOTE", "8, 3")]), "FEIDE", 8).unwrap();
+        let accounts: Vec<_> = accounts.iter().map(|a| (a.index, a.brukernavn.as_str(), a.passord.as_str(), a.kvote)).collect();
+        assert_eq!(accounts, [(0, "ola", "a", 8), (1, "kari", "b", 3)]);
+
+        let accounts = Account::loadAll(&secrets(&[("FEIDE_BRUKERNAVN", "ola"), ("FEIDE_PASSORD", "a")]), "FEIDE", 5).unwrap();
+        assert_eq!(accounts[0].kvote, 5);
+    }
+
+    #[test]
+    fn ugyldigeKontoa() {
+        let feil = |pairs: &[(&str, &str)]| Account::loadAll(&secrets(pairs), "FEIDE", 8).err().unwrap();
+
+        assert!(feil(&[("FEIDE_BRUKERNAVN", "ola,kari"), ("FEIDE_PASSORD", "a")]).contains("2 brukera, men FEIDE_PASSORD har 1 passord"));
+        assert!(feil(&[("FEIDE_BRUKERNAVN", "ola"), ("FEIDE_PASSORD", "a,b")]).contains("1 brukera, men FEIDE_PASSORD har 2 passord"));
+        assert!(feil(&[("FEIDE_BRUKERNAVN", "ola,kari"), ("FEIDE_PASSORD", "a,b"), ("FEIDE_KVOTE", "8,mange")]).contains("Ugyldig kvote"));
+        assert!(feil(&[("FEIDE_BRUKERNAVN", "ola,kari"), ("FEIDE_PASSORD", "a,b"), ("FEIDE_KVOTE", "8")]).contains("1 kvoter"));
+        assert!(feil(&[("FEIDE_BRUKERNAVN", "ola,,kari"), ("FEIDE_PASSORD", "a,b,c")]).contains("tomt brukernavn"));
+        assert!(feil(&[("FEIDE_BRUKERNAVN", " "), ("FEIDE_PASSORD", "a")]).contains("tomt brukernavn"));
+        assert_eq!(feil(&[("FEIDE_PASSORD", "a")]), "Sett FEIDE_BRUKERNAVN");
+    }
+}
@@ -0,0 +1,144 @@
+use std::collections::{HashMap, HashSet};
+
+use chrono::{Duration, NaiveDateTime, Timelike, Utc};
+use chrono_tz::Europe::Oslo;
+use maud::html;
+use rocket::{get, http::Status, response::content::{RawHtml, RawJson}, routes, uri, Route, State};
+use serde::Serialize;
+
+use crate::gruppe::Group;
+use crate::page;
+use crate::planner::{overlapper, tryOsloTilUtc};
+use crate::rom;
+
+/// Et rom som e ledig i heile tidsrommet
+#[derive(Debug, Serialize)]
+struct FreeRoom {
+    name: String,
+    building_name: String,
+    size: u16,
+    mazemap: String,
+}
+
+/// Svaret på /ledig.json. unknown e romman vi ikkje har timeplan for i det tidsrommet, så vi vet ikkje om dem e ledige.
+#[derive(Debug, Serialize)]
+struct FreeRooms {
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+    rooms: Vec<FreeRoom>,
+    unknown: Vec<String>,
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![freeRoomsPage, freeRoomsJson]
+}
+
+/// Tidspunkt fra et datetime-local input, eller default om det mangle
+fn parseTime(time: Option<&str>, default: NaiveDateTime) -> Result<NaiveDateTime, (Status, String)> {
+    match time.filter(|t| !t.is_empty()) {
+        None => Ok(default),
+        Some(t) => NaiveDateTime::parse_from_str(t, "%FT%H:%M")
+            .or_else(|_| NaiveDateTime::parse_from_str(t, "%FT%T"))
+            .map_err(|_| (Status::BadRequest, format!("Ugyldig tidspunkt {}, bruk YYYY-MM-DDTHH:MM", t))),
+    }
+}
+
+/// Alle romman i byggan te gruppene som e ledige fra from te to, størst først. Bruke bare timeplanan vi har lagra,
+/// så det sende ingen requests te TP. Uten from og to ser vi på den neste timen.
+async fn findFreeRooms(groups: &[Group], pool: &sqlx::PgPool, from: Option<&str>, to: Option<&str>) -> Result<FreeRooms, (Status, String)> {
+    let now = Utc::now().with_timezone(&Oslo).naive_local().with_second(0).unwrap().with_nanosecond(0).unwrap();
+    let from = parseTime(from, now)?;
+    let to = parseTime(to, from + Duration::hours(1))?;
+    if from >= to {
+        return Err((Status::BadRequest, "from må vær før to".to_string()));
+    }
+    let utc = |time: &NaiveDateTime| tryOsloTilUtc(time)
+        .ok_or_else(|| (Status::BadRequest, format!("{} finnes ikkje, for da stilles klokka fram", time.format("%F %H:%M"))));
+    let (fromUtc, toUtc) = (utc(&from)?, utc(&to)?);
+
+    let schedules: HashMap<String, rom::CachedSchedule> = rom::loadSchedules(pool).await.into_iter()
+        .map(|schedule| (schedule.room.clone(), schedule))
+        .collect();
+
+    let mut seen = HashSet::new();
+    let mut rooms = Vec::new();
+    let mut unknown = Vec::new();
+    for group in groups {
+        let catalog = rom::loadCatalog(pool, &group.policy).await;
+        for room in group.policy.withCatalog(&catalog).rom {
+            let roomId = group.policy.roomId(&room.navn);
+            if !seen.insert(roomId.clone()) {
+                continue;
+            }
+            match schedules.get(&roomId.to_string()) {
+                Some(schedule) if schedule.from_date <= from.date() && to.date() <= schedule.to_date => {
+                    let occupied = schedule.events.iter().any(|event| overlapper(&event.dtstart, &event.dtend, &fromUtc, &toUtc));
+                    if !occupied {
+                        rooms.push(FreeRoom {
+                            mazemap: uri!(crate::roomRedirect(&group.slug, &room.navn)).to_string(),
+                            name: room.navn,
+                            building_name: group.policy.bygning.clone(),
+                            size: room.kapasitet,
+                        });
+                    }
+                },
+                _ => unknown.push(room.navn),
+            }
+        }
+    }
+
+    rooms.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.name.cmp(&b.name)));
+    Ok(FreeRooms { from, to, rooms, unknown })
+}
+
+/// Hvilke rom som e ledige akkurat nå, eller i et anna tidsrom. Nyttig når nån har tatt rommet vårt.
+#[get("/ledig?<from>&<to>")]
+pub async fn freeRoomsPage(
+    from: Option<&str>,
+    to: Option<&str>,
+    groups: &State<Vec<Group>>,
+    pool: &State<sqlx::PgPool>
+) -> Result<RawHtml<String>, (Status, String)> {
+    let free = findFreeRooms(groups, pool, from, to).await?;
+    let fromStr = free.from.format("%FT%H:%M").to_string();
+    let toStr = free.to.format("%FT%H:%M").to_string();
+
+    Ok(page("Ledige rom", html!{
+        form class="flex flex-row justify-around h-12 pt-3" method="get" action=(uri!(freeRoomsPage(None::<&str>, None::<&str>))) {
+            input type="datetime-local" name="from" value=(fromStr);
+            input type="datetime-local" name="to" value=(toStr);
+            button type="submit" { "Vis" }
+        }
+        div class="flex flex-row justify-around h-12 pt-3 text-lg" {
+            span { "Rom" }
+            span { "Plassa" }
+            span { "Bygning" }
+        }
+        @for room in &free.rooms {
+            div class="flex flex-row justify-around h-12 pt-3" {
+                a href=(room.mazemap) { (room.name) }
+                span { (room.size) }
+                span { (room.building_name) }
+            }
+        }
+        @if free.rooms.is_empty() {
+            div class="pt-3" { "Fant ingen ledige rom." }
+        }
+        @if !free.unknown.is_empty() {
+            div class="pt-3" { "Vi har ikkje timeplanen te " (free.unknown.len()) " rom for den tida, så dem e ikkje med." }
+        }
+        div class="pt-3 pb-12" { a href=(uri!(freeRoomsJson(Some(&fromStr), Some(&toStr)))) { "JSON" } }
+    }))
+}
+
+/// Samme som /ledig, men som JSON
+#[get("/ledig.json?<from>&<to>")]
+async fn freeRoomsJson(
+    from: Option<&str>,
+    to: Option<&str>,
+    groups: &State<Vec<Group>>,
+    pool: &State<sqlx::PgPool>
+) -> Result<RawJson<String>, (Status, String)> {
+    let free = findFreeRooms(groups, pool, from, to).await?;
+    Ok(RawJson(serde_json::to_string(&free).unwrap()))
+}
@@ -1,24 +1,50 @@
 #![allow(non_snake_case)]
 
-use std::sync::Arc;
+use std::{collections::{HashMap, HashSet}, future::Future, sync::Arc};
 
-use maud::{html, DOCTYPE};
-use rocket::{fs::{relative, FileServer}, get, response::{content::RawHtml, Redirect}, routes, uri, State};
+use maud::{html, Markup, DOCTYPE};
+use rocket::{fs::{relative, FileServer}, get, response::{content::RawHtml, Redirect}, routes, uri, Either, State};
 
 use shuttle_rocket::ShuttleRocket;
 use shuttle_runtime::SecretStore;
 
-use reqwest::{cookie::{self, CookieStore}, Client, Url};
+use reqwest::{cookie, Client, Url};
 
-use serde_json::{json, Value};
+use serde_json::Value;
 
-use chrono::{DateTime, Datelike, Days, Duration as ChronoDuration, Locale, NaiveDate, NaiveDateTime, Timelike, Utc};
+use chrono::{DateTime, Days, Duration as ChronoDuration, Locale, NaiveDate, NaiveDateTime, Utc};
 
 use scraper::{Html, Selector};
 
+use futures::{stream, StreamExt};
+
 use tokio::{task, time::{interval, Duration as TokioDuration}};
+mod admin;
+mod bestilling;
+mod fixtures;
+mod gruppe;
+mod helligdager;
+mod historikk;
+mod konto;
+mod ledig;
+mod mocktp;
+mod planner;
+mod policy;
+mod rom;
+mod sesjon;
+mod tp;
 mod update;
 
+use crate::bestilling::{RequestStatus, BookingRequest};
+use crate::fixtures::RecordReplay;
+use crate::gruppe::Group;
+use crate::historikk::{AttemptKind, BookingLog};
+use crate::sesjon::Login;
+use crate::konto::Account;
+use crate::planner::{BookingPlan, ExistingBooking, Quota};
+use crate::policy::BookingPolicy;
+use crate::rom::{CachedSchedule, CatalogRoom};
+use crate::tp::{ClientMethods, ReservationError, RoomId, TpBooking, TpError, TpRoomEvent, TpRoomSchedule};
 use crate::update::send_email;
 
 #[derive(sqlx::FromRow)]
@@ -62,293 +88,462 @@ impl GetSet for sqlx::PgPool {
 }
 
 
-const ROM_PRIORITERING: [&str; 34] = [
-    // Disse tre første trur e ikkje e har tilgang te å book
-    // "04-065", // Originale HelgaSangern, 40 plassa, undervisningsrom
-    // "03-023", // 50 plassa, undervisningsrom
-    // "03-058", // 50 plassa, undervisningsrom
-    
-    "04-072", // 30 plassa, grupperom, veldig bra rom, skikkelig utsikt
-    "03-047", // 30 plassa, grupperom
-    "04-023", // 25 plassa, undervisningsrom, bra rom
-    "03-033", // 30 plassa, grupperom, meh
-    "04-067", // 30 plassa, undervisningsrom
-    "03-045", // 26 plassa, undervisningsrom, kjipt rom
-    "04-086", // 18 plassa, grupperom, glassrom med få ladeplassa
-    "05-118", // 16 plassa, grupperom
-    "05-119", // 16 plassa, grupperom
-
-    // Generert av getRooms.py, 15-10 plassa nedover
-    "04-075",
-    "03-075",
-    "03-074",
-    "03-063",
-    "03-062",
-    "03-061",
-    "04-093",
-    "02-045",
-    "06-034",
-    "05-126",
-    "05-121",
-    "05-114",
-    "04-094",
-    "04-087",
-    "04-077",
-    "04-074",
-    "03-087",
-    "03-086",
-    "03-085",
-    "03-064",
-    "03-032",
-    "02-043",
-    "02-042",
-    "02-041",
-    "03-068",
-
-    // // For testing, book dem minste romman vi finn
-    // "05-063", 
-    // "04-088", 
-    // "04-089",
-    // "04-091",
-    // "04-092",
-    // "04-082",
-    // "04-098",
-    // "04-099",
-    // "03-078",
-    // "05-115",
-    // "05-116",
-    // "03-081",
-];
-
-const BOOKING_NAVN: &str = "HelgaSangern Kollokvie";
-const BOOKING_UKEDAGER: [u8; 5] = [0, 1, 2, 3, 4];
-
-/// Gitt en datetime generere denne neste datetime vi ønske å book på:)
-fn getNextBooking(
-    dateTime: &NaiveDateTime
-) -> NaiveDateTime {
-    if dateTime.hour() < 12 {
-        dateTime.with_hour(13).unwrap().with_minute(0).unwrap()
-    } else {
-        let mut newDateTime = dateTime.with_hour(8).unwrap().with_minute(30).unwrap();
-        newDateTime = newDateTime.checked_add_days(Days::new(1)).unwrap();
-        while !BOOKING_UKEDAGER.contains(&(newDateTime.weekday().num_days_from_monday() as u8)){
-            newDateTime = newDateTime.checked_add_days(Days::new(1)).unwrap();
-        }
-        newDateTime
-    }
+/// Slutten på en booking fra getBookings. tid ser ut som "08:30 - 12:30", og om den ikkje gjør det
+/// bruke vi økta i policyen som starte samtidig. Finn vi ingen av delan bruke vi start, altså ei tom booking.
+fn bookingEnd(policy: &BookingPolicy, booking: &TpBooking) -> NaiveDateTime {
+    let start = booking.booked;
+    let fromPolicy = || policy.sessionsOn(&start.date()).into_iter().find(|(s, _)| *s == start).map(|(_, end)| end);
+
+    booking.end().or_else(fromPolicy).unwrap_or(start)
 }
 
-// API hjelpemetoder
-trait ClientMethods {
-    // async fn login(&self, secretStore: &SecretStore, pool: &sqlx::PgPool);
-    async fn getBookings(&self) -> Result<Value, reqwest::Error>;
-    async fn getScheduleForRoom(&self, room: &str, startDate: NaiveDate, endDate: NaiveDate) -> Result<Value, reqwest::Error>;
-    async fn bookRoom(&self, roomName: &str, dateTime: &NaiveDateTime) -> Result<reqwest::Response, reqwest::Error>;
+/// Skaffe alle avlyste dager fra og med idag for en gruppe
+async fn getCancelledDays(pool: &sqlx::PgPool, gruppe: &str) -> HashSet<NaiveDate> {
+    sqlx::query_scalar("SELECT day FROM cancelled_days WHERE gruppe = $1 AND day >= $2")
+        .bind(gruppe)
+        .bind(fixtures::now().date_naive())
+        .fetch_all(pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .collect()
 }
 
-impl ClientMethods for Client {
-    /// Skaffe våre egne bookinga
-    async fn getBookings(&self) -> Result<Value, reqwest::Error> {
-        self.get("https://tp.educloud.no/ntnu/ws/rombestilling/bookings.php")
-                .header("accept", "application/json")
-                .send().await.unwrap().json().await
+/// Avbestill alle bookingan te gruppa på avlyste dager, sånn at andre kan få romman
+async fn releaseCancelledBookings(group: &Group, pool: &sqlx::PgPool) {
+    let cancelledDays = getCancelledDays(pool, &group.slug).await;
+    if cancelledDays.is_empty() {
+        return;
     }
 
-    /// Skaffe timeplan for et spesifikt rom
-    async fn getScheduleForRoom(&self, room: &str, startDate: NaiveDate, endDate: NaiveDate) -> Result<Value, reqwest::Error> {
-        self.get(format!("https://tp.educloud.no/ntnu/ws/1.4/room.php?id=250{}&fromdate={}&todate={}&lang=no&split_intervals=false",
-                room, startDate.format("%F"), endDate.format("%F")
-            ))
-            .header("accept", "application/json")
-            .send().await.unwrap().json().await
+    for account in &group.accounts {
+        let (client, bookings) = match getClientAndBookings(account, &group.policy, pool, Login::Inline).await {
+            Ok(clientAndBookings) => clientAndBookings,
+            Err(e) => {
+                println!("Fikk ikkje sjekka bookingan te {} for avlyste dager: {}", account.brukernavn, e);
+                continue;
+            },
+        };
+
+        for booking in bookings.iter().filter(|b| b.name == group.policy.navn) {
+            let day = booking.firstday;
+            if !cancelledDays.contains(&day) {
+                continue;
+            }
+
+            let roomName = booking.room();
+            match client.cancelBooking(&group.policy.institusjon, &booking.id).await {
+                Ok(()) => println!("Avbestilte {} {} fordi dagen e avlyst", day, roomName),
+                Err(e) => println!("Fikk ikkje avbestilt {} {}: {}", day, roomName, e),
+            }
+        }
     }
+}
 
-    /// Booke faktisk rom på det tidspunktet
-    async fn bookRoom(&self, roomName: &str, dateTime: &NaiveDateTime) -> Result<reqwest::Response, reqwest::Error>{
-        self.post("https://tp.educloud.no/ntnu/ws/rombestilling/reservation.php")
-            .header("accept", "application/json")
-            .body(json!({
-                "start": format!("{}", dateTime.format("%FT%T")),
-                "end": format!("{}", dateTime.checked_add_signed(ChronoDuration::hours(4)).unwrap().format("%FT%T")),
-                "rooms": [format!("250{}", roomName)], // Romnavn formateres som campus/byggnavn (250 for Helgasetr), etterfulgt av navnet
-                "name": BOOKING_NAVN,
-                "notes": "",
-                "userGroup": null
-            }).to_string())
-            .send().await
+/// Hent romkatalogen fra TP og lagre den i databasen. Om det feile bruke vi bare katalogen fra forrige gang. 
+async fn importRoomCatalog(group: &Group, pool: &sqlx::PgPool) {
+    let catalog = match getClientAndBookings(&group.accounts[0], &group.policy, pool, Login::Inline).await {
+        Ok((client, _bookings)) => client.getRoomCatalog(&group.policy.institusjon).await,
+        Err(e) => Err(e),
+    };
+    match catalog {
+        Ok(json) => {
+            let rooms = CatalogRoom::fromTp(&json, &group.policy);
+            if rooms.is_empty() {
+                println!("Fant ingen rom i romkatalogen, beholde den gamle");
+                return;
+            }
+            match rom::saveCatalog(pool, &group.policy, &rooms).await {
+                Ok(()) => println!("Importerte {} rom fra romkatalogen", rooms.len()),
+                Err(e) => println!("Fikk ikkje lagra romkatalogen: {}", e),
+            }
+        },
+        Err(e) => println!("Fikk ikkje henta romkatalogen: {}", e),
     }
 }
 
-/// En metode som kjøre heile rombookingsprosessen, heilt fra vi har en innlogget client, 
-/// til å finn ut hvilke rom vi skal booke, til å faktisk booke dem. 
-async fn bookRooms(secretStore: &SecretStore, pool: &sqlx::PgPool) {
+// Hvor mange timeplana vi hente fra TP samtidig
+const SCHEDULE_FETCH_PARALLELISM: usize = 8;
+
+/// Skaffe timeplanen te alle romman i policyen fra i dag og horisonten ut. Timeplana i databasen som e nyere enn maxAge
+/// brukes som dem e, resten hentes fra TP, SCHEDULE_FETCH_PARALLELISM om gangen, og lagres i databasen.
+/// Om TP ikkje svare bruke vi den gamle timeplanen om vi har en. I databasen ligg timeplanan under RoomId-en te rommet,
+/// sånn at like romnavn i forskjellige bygg ikkje blandes, men planneren får dem under romnavnet.
+async fn getRoomSchedules(client: &Client, pool: &sqlx::PgPool, policy: &BookingPolicy, maxAge: ChronoDuration) -> HashMap<String, Vec<TpRoomEvent>> {
+    let fromDate = fixtures::now().date_naive();
+    let toDate = fromDate.checked_add_days(Days::new(policy.horisontDager.into())).unwrap();
+
+    let mut cached: HashMap<String, CachedSchedule> = rom::loadSchedules(pool).await.into_iter()
+        .map(|schedule| (schedule.room.clone(), schedule))
+        .collect();
+
+    let mut roomSchedules: HashMap<String, Vec<TpRoomEvent>> = HashMap::new();
+    let mut outdated: Vec<RoomId> = Vec::new();
+    for room in policy.rom.iter() {
+        let roomId = policy.roomId(&room.navn);
+        match cached.get(&roomId.to_string()) {
+            Some(schedule) if schedule.isFresh(fromDate, toDate, maxAge) => { roomSchedules.insert(room.navn.clone(), schedule.events.0.clone()); },
+            _ => outdated.push(roomId),
+        }
+    }
+
+    let fetched: Vec<(RoomId, Result<TpRoomSchedule, TpError>)> = stream::iter(outdated)
+        .map(|room| async move {
+            let result = client.getScheduleForRoom(&room, fromDate, toDate).await;
+            (room, result)
+        })
+        .buffer_unordered(SCHEDULE_FETCH_PARALLELISM)
+        .collect()
+        .await;
+
+    for (room, result) in fetched {
+        match result {
+            Ok(schedule) => {
+                // Får vi ikkje lagra den har vi den fortsatt, så da bli det bare henta på nytt neste gang
+                if let Err(e) = rom::saveSchedule(pool, &room.to_string(), &schedule.events, fromDate, toDate).await {
+                    println!("Fikk ikkje lagra timeplanen te {}: {}", room, e);
+                }
+                roomSchedules.insert(room.rom, schedule.events);
+            },
+            Err(e) => {
+                println!("Fikk ikkje henta timeplanen te {}: {}", room, e);
+                if let Some(schedule) = cached.remove(&room.to_string()) {
+                    roomSchedules.insert(room.rom, schedule.events.0);
+                }
+            },
+        }
+    }
+    roomSchedules
+}
+
+/// Logge inn alle clients og lag en plan for ka vi ska book, uten å book nåkka. 
+/// Returne clientsa og kvoten te hver client også, sånn at bookRooms kan bruk dem te å gjennomfør planen. 
+/// Timeplanan som e nyere enn scheduleMaxAge hentes ikkje på nytt. login sier om vi får logge inn med feide underveis.
+async fn makeBookingPlan(group: &Group, pool: &sqlx::PgPool, scheduleMaxAge: ChronoDuration, login: Login) -> Result<(Vec<Client>, Vec<Quota>, BookingPlan), TpError> {
+    let policy = &group.policy.withCatalog(&rom::loadCatalog(pool, &group.policy).await);
+
     let mut clients: Vec<Client> = Vec::new();
-    let mut clientBookings: Vec<Vec<Value>> = Vec::new();
-    let mut bookingsCount: Vec<u8> = Vec::new();
+    let mut clientBookings: Vec<Vec<TpBooking>> = Vec::new();
+    let mut quotas: Vec<Quota> = Vec::new();
 
-    for clientIndex in 0..NUM_LOGINS {
-        let (client, booking) = getClientAndBookings(&secretStore, &pool, clientIndex.into()).await;
+    for account in &group.accounts {
+        let (client, booking) = getClientAndBookings(account, &group.policy, pool, login).await?;
         clients.push(client);
-        bookingsCount.push(booking.len().try_into().unwrap());
+        quotas.push(Quota { used: booking.len().try_into().unwrap_or(u8::MAX), max: account.kvote });
         clientBookings.push(booking);
     }
 
-    let today = Utc::now().date_naive();
-
-    let mut bookingTimes: Vec<NaiveDateTime> = Vec::new();
-
     // Se på egne bookings
-    for booking in clientBookings.clone().into_iter().flat_map(|v| v) {
-        if booking.get("name").unwrap().as_str().unwrap() == BOOKING_NAVN {
-            let currDate = NaiveDateTime::parse_from_str(booking.get("booked").unwrap().as_str().unwrap(), "%F %T").unwrap();
-            bookingTimes.push(currDate);
+    let existingBookings: Vec<ExistingBooking> = clientBookings.iter().enumerate()
+        .flat_map(|(clientIndex, bookings)| bookings.iter().map(move |booking| (clientIndex, booking)))
+        .filter(|(_, booking)| booking.name == policy.navn)
+        .map(|(clientIndex, booking)| ExistingBooking {
+            id: booking.id.clone(),
+            clientIndex,
+            start: booking.booked,
+            end: bookingEnd(policy, booking),
+            room: booking.room().to_string(),
+        })
+        .collect();
+
+    let roomSchedules = getRoomSchedules(&clients[0], pool, policy, scheduleMaxAge).await;
+
+    let cancelledDays = getCancelledDays(pool, &group.slug).await;
+
+    let now = planner::osloNaa();
+    let requests: Vec<BookingRequest> = bestilling::pendingRequests(pool, &group.slug).await;
+
+    let plan = planner::planBookings(policy, &existingBookings, &quotas, &roomSchedules, &cancelledDays, &requests, &now);
+    Ok((clients, quotas, plan))
+}
+
+/// En metode som kjøre heile rombookingsprosessen, heilt fra vi har en innlogget client, 
+/// til å finn ut hvilke rom vi skal booke, til å faktisk booke dem. 
+/// Returne hvor mange planlagte økter vi ikkje fikk booka, sånn at det kan prøves igjen.
+async fn bookRooms(group: &Group, pool: &sqlx::PgPool) -> usize {
+    let policy = &group.policy;
+    let log = BookingLog::start(pool, &group.slug).await;
+    // Gjøres her og ikkje i makeBookingPlan, for /plan ska ikkje endre nåkka
+    bestilling::expireRequests(pool, &group.slug, &planner::osloNaa()).await;
+
+    // Vi treng ferske timeplana når vi faktisk booke, spesielt rett etter at horisonten åpne
+    let (clients, mut quotas, plan) = match makeBookingPlan(group, pool, ChronoDuration::minutes(1), Login::Inline).await {
+        Ok(plan) => plan,
+        Err(e) => {
+            // Vi vet ikkje hvor mange økter vi gikk glipp av, men det e verdt å prøv igjen
+            log.log(AttemptKind::Failed, None, None, None, format!("Fikk ikkje laga en plan: {}", e)).await;
+            return 1;
+        },
+    };
+
+    // Bytt te bedre rom der det har blitt ledig. Det nye rommet bookes før det gamle avbestilles, 
+    // sånn at vi aldri står uten rom om nåkka går galt. 
+    for upgrade in plan.upgrades {
+        let booking = &upgrade.booking;
+        let slot = Some((&booking.start, &booking.end));
+        let roomId = policy.roomId(&upgrade.room);
+        match clients[upgrade.clientIndex].bookRoom(&policy.navn, &roomId, &booking.start, &booking.end).await {
+            Ok(_) => {
+                quotas[upgrade.clientIndex].used += 1;
+                match clients[booking.clientIndex].cancelBooking(&policy.institusjon, &booking.id).await {
+                    Ok(()) => {
+                        quotas[booking.clientIndex].used -= 1;
+                        claimRoom(pool, &policy.navn, &roomId, &booking.start, &booking.end).await;
+                        log.log(AttemptKind::Upgraded, slot, Some(&upgrade.room), Some(upgrade.clientIndex),
+                            format!("Bytta fra {}", booking.room)).await;
+                    },
+                    Err(e) => log.log(AttemptKind::Upgraded, slot, Some(&upgrade.room), Some(upgrade.clientIndex),
+                        format!("Booka, men fikk ikkje avbestilt {}: {}", booking.room, e)).await,
+                }
+            },
+            Err(e) => log.log(AttemptKind::Failed, slot, Some(&upgrade.room), Some(upgrade.clientIndex),
+                format!("Fikk ikkje bytta fra {}: {}", booking.room, e)).await,
         }
     }
-    bookingTimes.sort_by(|a, b| a.partial_cmp(b).unwrap());
-
-    // println!("bookingTimes: {:#?}", bookingTimes);
 
-    // Generer liste av ting vi ønske å booke
-    let mut newBookingTimes: Vec<NaiveDateTime> = Vec::new();
-    newBookingTimes.push(bookingTimes.last().unwrap_or(&Utc::now().naive_local()).clone());
-    for _i in 0..(MAX_BOOKINGS - bookingsCount.iter().sum::<u8>()) {
-        newBookingTimes.push(getNextBooking(newBookingTimes.last().unwrap()))
+    if plan.quotaShortfall > 0 {
+        log.log(AttemptKind::Quota, None, None, None, format!("Kvoten e brukt opp, mangle {} økter", plan.quotaShortfall)).await;
     }
-    newBookingTimes.remove(0);
 
-    println!("newBookingTimes: {:#?}", newBookingTimes);
-
-    // Genrer liste av når romman e ledig
-    let mut romSchedules:Vec<Value>  = Vec::new();
+    let mut failed = 0;
+    let mut quotaFull = false;
+    let mut slots = plan.slots.into_iter();
+    'slots: for slot in slots.by_ref() {
+        let slotTime = Some((&slot.start, &slot.end));
+        log.log(AttemptKind::Slot, slotTime, slot.room.as_deref(), slot.clientIndex,
+            if slot.room.is_some() { format!("{} andre ledige", slot.alternatives.len()) } else { "Ingen ledige rom".to_string() }).await;
+        for rejected in slot.rejected.iter() {
+            log.log(AttemptKind::Rejected, slotTime, Some(&rejected.room), None, &rejected.reason).await;
+        }
 
-    // Må bruk while i stedet for en for loop fordi størrelsen endre seg ila loopinga. E funksjonelt sett en for loop:)
-    let mut newBookingTimesIndex = 0;
-    while newBookingTimesIndex < newBookingTimes.len() {
-        let newBookingTime = newBookingTimes.get(newBookingTimesIndex).unwrap();
-        let mut booked = false;
+        let (Some(roomName), Some(mut clientIndex)) = (slot.room, slot.clientIndex) else {
+            if let Some(request) = slot.request {
+                bestilling::updateRequest(pool, request, RequestStatus::Venter, None, "Ingen ledige rom enda").await;
+            }
+            continue
+        };
+
+        // Planen kan ha blitt utdatert om en tidligere booking måtte bytt login
+        if quotas[clientIndex].free() == 0 {
+            match planner::pickClient(&quotas) {
+                Some(i) => clientIndex = i,
+                None => { quotaFull = true; break 'slots },
+            }
+        }
 
-        for (roomIndex, roomName) in ROM_PRIORITERING.iter().enumerate() {
-            if roomIndex >= romSchedules.len() {
-                // Her har vi ikkje sendt request for dette rommet ennå
-                romSchedules.push(clients[0].getScheduleForRoom(roomName, today, today.checked_add_days(Days::new(14)).unwrap())
-                .await.unwrap().get("events").unwrap().clone())
+        // Bestillinga fra skjemaet bookes med et eget navn, sånn at dem ikkje blande seg med dem faste øktene
+        let name = if slot.request.is_some() { policy.extraNavn() } else { policy.navn.clone() };
+
+        // Prøv romman i prioritert rekkefølge helt til TP faktisk gir oss et av dem
+        let mut rooms = std::iter::once(roomName).chain(slot.alternatives);
+        let mut room = rooms.next();
+        while let Some(roomName) = &room {
+            let roomId = policy.roomId(roomName);
+            match clients[clientIndex].bookRoom(&name, &roomId, &slot.start, &slot.end).await {
+                Ok(response) => {
+                    log.log(AttemptKind::Booked, slotTime, Some(roomName), Some(clientIndex), response).await;
+                    claimRoom(pool, &name, &roomId, &slot.start, &slot.end).await;
+                    if let Some(request) = slot.request {
+                        bestilling::updateRequest(pool, request, RequestStatus::Booka, Some(roomName), "").await;
+                    }
+                    quotas[clientIndex].used += 1;
+                    break;
+                },
+                Err(TpError::Refused(ReservationError::Quota(msg))) => {
+                    // Denne brukeren e full, så prøv samme rom med en annen bruker
+                    log.log(AttemptKind::Quota, slotTime, Some(roomName), Some(clientIndex), msg).await;
+                    quotas[clientIndex].used = quotas[clientIndex].max;
+                    match planner::pickClient(&quotas) {
+                        Some(i) => clientIndex = i,
+                        None => { quotaFull = true; break 'slots },
+                    }
+                },
+                Err(e) => {
+                    log.log(AttemptKind::Failed, slotTime, Some(roomName), Some(clientIndex), &e).await;
+                    room = rooms.next();
+                    if room.is_none() {
+                        failed += 1;
+                        if let Some(request) = slot.request {
+                            bestilling::updateRequest(pool, request, RequestStatus::Venter, None, &e.to_string()).await;
+                        }
+                    }
+                },
             }
+        }
+    }
+
+    // Om alle loginan ble fulle underveis stoppe vi, og rapportere hvor mange økter vi ikkje fikk booka
+    if quotaFull {
+        let notBooked = 1 + slots.filter(|slot| slot.room.is_some()).count();
+        log.log(AttemptKind::Quota, None, None, None, format!("Alle loginan e fulle, fikk ikkje booka {} økter", notBooked)).await;
+        failed += notBooked;
+    }
+    failed
+}
+
+/// Marker rommet som opptatt i den lagra timeplanen, sånn at gruppene som booke etter oss i samme kjøring
+/// ikkje prøve å ta det, sjøl om timeplanen dems e henta før vi booka.
+async fn claimRoom(pool: &sqlx::PgPool, name: &str, room: &RoomId, start: &NaiveDateTime, end: &NaiveDateTime) {
+    let event = TpRoomEvent {
+        summary: name.to_string(),
+        dtstart: planner::osloTilUtc(start),
+        dtend: planner::osloTilUtc(end),
+    };
+    if let Err(e) = rom::addToSchedule(pool, &room.to_string(), event).await {
+        println!("Fikk ikkje lagt {} {} te i timeplanen: {}", start, room, e);
+    }
+}
 
-            let roomSchedule = romSchedules.get(roomIndex).unwrap().as_array().unwrap().clone();
+/// Det som må gjøres før bookRooms, men som tar for lang tid te å gjøres mellom forsøkan når horisonten åpne:
+/// avbestill bookingan på avlyste dager og hent romkatalogen på nytt.
+async fn prepareGroups(groups: &[Group], pool: &sqlx::PgPool) {
+    for group in groups {
+        releaseCancelledBookings(group, pool).await;
+        importRoomCatalog(group, pool).await;
+    }
+}
 
-            // println!("roomSchedule: {:#?}", roomSchedule);
+/// Vent te et tidspunkt, eller ingenting om det allerede har vært
+async fn sleepUntil(time: DateTime<Utc>) {
+    tokio::time::sleep((time - Utc::now()).to_std().unwrap_or_default()).await;
+}
 
-            // Her vil hvert element i roomSchedule vær en liste av events, og romSchedules har alle rom te og med nåværende rommet. 
+/// Kjøre bookRooms for alle gruppene etter tur. Gruppa som får velg først bytte for hver kjøring,
+/// sånn at ingen gruppe alltid får dem beste romman. Returne hvor mange økter vi ikkje fikk booka til sammen.
+async fn bookRoomsForGroups(groups: &[Group], pool: &sqlx::PgPool, round: usize) -> usize {
+    let mut failed = 0;
+    for i in 0..groups.len() {
+        failed += bookRooms(&groups[(round + i) % groups.len()], pool).await;
+    }
+    failed
+}
 
-            let mut isFree = true;
+/// Kjøre en bookingjobb i en egen task, sånn at en panic, f.eks. fordi databasen ikkje svarte, bare stoppe denne kjøringa
+/// og ikkje heile bookingScheduler. En panic telle som én økt vi ikkje fikk booka, sånn at det prøves igjen.
+async fn supervised<Job, Fut>(groups: &[Group], pool: &sqlx::PgPool, job: Job) -> usize
+where
+    Job: FnOnce(Vec<Group>, sqlx::PgPool) -> Fut,
+    Fut: Future<Output = usize> + Send + 'static,
+{
+    match task::spawn(job(groups.to_vec(), pool.clone())).await {
+        Ok(failed) => failed,
+        Err(e) => {
+            let reason = e.try_into_panic().ok()
+                .and_then(|panic| panic.downcast_ref::<&str>().map(|r| r.to_string()).or_else(|| panic.downcast_ref::<String>().cloned()))
+                .unwrap_or_default();
+            println!("Bookinga panica: {}", reason);
+            1
+        },
+    }
+}
 
-            // println!("roomSchedule: {}", roomSchedule.to_string());
+/// Kjøre bookRooms hver fjerde time, og i tillegg rett etter at TP åpne en ny dag for booking.
+/// Da e det kamp om romman, så vi logge inn litt før og prøve flere gang rett etter åpninga.
+/// Gruppene kjøres etter hverandre og aldri samtidig, sånn at dem ikkje prøve å ta samme rom.
+async fn bookingScheduler(groups: &[Group], pool: &sqlx::PgPool) {
+    let regularInterval = ChronoDuration::hours(4);
+    let mut nextRegularRun = Utc::now();
+    let mut round = 0;
+    loop {
+        let now = Utc::now();
+        let (nextOpening, openingGroup) = groups.iter()
+            .map(|group| (planner::nextHorizonOpening(&group.policy, &now), group))
+            .min_by_key(|(opening, _)| *opening)
+            .unwrap();
 
-            // Sjekk om nån events i rommet skjer idag. Isåfall, continue. 
-            for event in roomSchedule {
-                let start = DateTime::parse_from_str(event.get("dtstart").unwrap().as_str().unwrap(), "%FT%T%#z").unwrap();
-                let end = DateTime::parse_from_str(event.get("dtend").unwrap().as_str().unwrap(), "%FT%T%#z").unwrap();
-                let bookingName = event.get("summary").unwrap().as_str().unwrap();
-                // println!("Conditionals: {} {} {} ", bookingName != bookingNavn, start.date_naive() <= newBookingTime.date(), newBookingTime.date() <= end.date_naive());
-                // println!("Booking: {} {} {}", bookingName, start.date_naive(), end.date_naive());
+        if nextOpening > nextRegularRun {
+            sleepUntil(nextRegularRun).await;
+            println!("Running cron job");
+            supervised(groups, pool, |groups, pool| async move {
+                prepareGroups(&groups, &pool).await;
+                bookRoomsForGroups(&groups, &pool, round).await
+            }).await;
+        } else {
+            // Sørg for at cookiesa e gyldige, sånn at vi ikkje bruke tid på feide innlogging når dagen åpne.
+            // Romkatalogen og avlyste dager tas også nå, sånn at første reservasjon kan sendes med en gang.
+            sleepUntil(nextOpening - ChronoDuration::minutes(1)).await;
+            supervised(groups, pool, |groups, pool| async move {
+                prepareGroups(&groups, &pool).await;
+                for group in &groups {
+                    for account in &group.accounts {
+                        if let Err(e) = getClientAndBookings(account, &group.policy, &pool, Login::Inline).await {
+                            println!("Fikk ikkje logga inn {} før horisonten åpna: {}", account.brukernavn, e);
+                        }
+                    }
+                }
+                0
+            }).await;
 
-                if bookingName != BOOKING_NAVN && start.date_naive() <= newBookingTime.date() && newBookingTime.date() <= end.date_naive() {
-                    isFree = false;
+            sleepUntil(nextOpening).await;
+            for forsok in 0..=openingGroup.policy.horisontForsok {
+                println!("Booking når horisonten åpne, forsøk {}", forsok + 1);
+                if supervised(groups, pool, |groups, pool| async move { bookRoomsForGroups(&groups, &pool, round).await }).await == 0 {
                     break;
                 }
+                tokio::time::sleep(TokioDuration::from_secs(openingGroup.policy.horisontPause)).await;
             }
-
-            if !isFree {
-                println!("Not free: {}", roomName);
-                continue;
-            }
-
-            // Logikk som velge ledig client
-            let clientIndex = (0..NUM_LOGINS).into_iter()
-                .filter(|i| bookingsCount[i.clone() as usize] < BOOKINGS_PER_LOGIN)
-                .next().unwrap() as usize;
-
-            let client = &clients[clientIndex];
-
-            println!("Free: {}", roomName);
-            // Om ingen events i det rommet skjer på denne dagen, book det
-            println!("Booking response: {:#?}", client.bookRoom(roomName, &newBookingTime).await.unwrap().text().await.unwrap());
-            
-            bookingsCount[clientIndex] += 1;
-
-            booked = true;
-            break;
-        }
-
-        if !booked {
-            // Om du ikkje kunna book en booking
-            newBookingTimes.push(getNextBooking(newBookingTimes.last().unwrap()));
         }
-
-        newBookingTimesIndex += 1;
+        round += 1;
+        nextRegularRun = Utc::now() + regularInterval;
     }
 }
 
 
-const KEY_TP_COOKIE_PREFIX: &str = "TP_COOKIE_KEY_";
-const NUM_LOGINS: u8 = 2;
-const BOOKINGS_PER_LOGIN: u8 = 8;
-const MAX_BOOKINGS: u8 = NUM_LOGINS * BOOKINGS_PER_LOGIN;
-
 /// Returne og sett cookies for en innlogget reqwest::Client. 
 /// Denne tråkke gjennom dem samme requestsa kæm som helst andre gjør når dem logge inn med feide. 
 /// Det funke, men bli fort ødelagt det øyeblikket noko som helst med feide innlogginga endre seg. 
 /// Samtidig e det trolig ikkje så ofte for en så stor og viktig tjeneste. 
-async fn newClient(secretStore: &SecretStore, pool: &sqlx::PgPool, userIndex: usize) -> Client {
-    println!("Logging in client {}!", userIndex.to_string());
+/// Den nye cookien lagres med sesjon::save.
+async fn newClient(account: &Account, policy: &BookingPolicy, pool: &sqlx::PgPool) -> Result<Client, TpError> {
+    println!("Logging in client {}!", account.brukernavn);
+    let unexpected = |msg: &str| TpError::UnexpectedPayload(format!("feide innlogging: {}", msg));
 
     let cookie_store = Arc::new(cookie::Jar::default());
     let client = Client::builder() // Den følge redirects by default
         .cookie_provider(cookie_store.clone())
-        .build()
-        .unwrap();
+        .build()?;
 
     // Først må vi redirectes fra login lenka te dataporten
-    let res1 = client.get("https://tp.educloud.no/ntnu/?login=1")
-        .send().await.unwrap();
+    let res1 = client.get(format!("{}/?login=1", tp::institutionUrl(&policy.institusjon)))
+        .sendRecorded().await?;
 
     // Da kjem vi te en side der vi ska velg universitet. 
     // For å gjør det kan vi bare følg returnTo query parameter (etter å ha urldecoda det)
-    // og hiv på authselection=feide|realm|ntnu.no
-    let mut req2Url = String::from(res1.url().query().unwrap()
-        .split("&").filter(|q|q.starts_with("returnTo")).next().unwrap()
-        .split("=").nth(1).unwrap());
+    // og hiv på authselection=feide|realm|ntnu.no, eller realmen te institusjonen i policyen
+    let returnTo = res1.url().query()
+        .and_then(|query| query.split('&').find(|q| q.starts_with("returnTo")))
+        .and_then(|q| q.split('=').nth(1))
+        .ok_or_else(|| unexpected("fant ikkje returnTo"))?;
 
-    req2Url = urlencoding::decode(&req2Url).unwrap().into_owned();
+    let mut req2Url = urlencoding::decode(returnTo).map_err(|_| unexpected("ugyldig returnTo"))?.into_owned();
 
-    req2Url.push_str("&authselection=feide|realm|ntnu.no");
+    req2Url.push_str(&format!("&authselection=feide|realm|{}", policy.feideRealm()));
 
     // Request login formet
-    let res2 = client.get(req2Url).send().await.unwrap();
+    let res2 = client.get(req2Url).sendRecorded().await?;
 
     // Log inn
     let res3 = client.post(res2.url().as_str()) // Postes te nøyaktig samme addresse
         .header("Content-Type", "application/x-www-form-urlencoded") // Må sett denne for at servern ska les form body
         .body(format!("has_js=0&feidename={}&password={}", 
-            secretStore.get("FEIDE_BRUKERNAVN").unwrap().split(',').nth(userIndex).unwrap(), 
-            urlencoding::encode(&secretStore.get("FEIDE_PASSORD").unwrap().split(',').nth(userIndex).unwrap()).into_owned())
+            account.brukernavn, 
+            urlencoding::encode(&account.passord).into_owned())
         )
-        .send().await.unwrap();
+        .sendRecorded().await?;
 
     // Plukk ut form data for å kunna generer request body
-    let formAction;
-    let SAMLResponse;
-    let RelayState;
-
-    { // Gjør funky scope greier her fordi ellers kan vi ikkje ha referansa te element, e scraper som e kjip
-        let page = Html::parse_document(&res3.text().await.unwrap());
-
-        let form = page.select(&Selector::parse("form").unwrap()).nth(0).unwrap();
-        formAction = String::from(form.attr("action").unwrap());
-        SAMLResponse = String::from(form.clone().select(&Selector::parse("input[name=\"SAMLResponse\"]").unwrap()).nth(0).unwrap().attr("value").unwrap());
-        RelayState = String::from(form.clone().select(&Selector::parse("input[name=\"RelayState\"]").unwrap()).nth(0).unwrap().attr("value").unwrap());
-    }
+    // Gjør funky scope greier her fordi ellers kan vi ikkje ha referansa te element, e scraper som e kjip
+    let (formAction, SAMLResponse, RelayState) = {
+        let page = Html::parse_document(&res3.text().await?);
+
+        let form = page.select(&Selector::parse("form").unwrap()).next();
+        let input = |name: &str| form?.select(&Selector::parse(&format!("input[name=\"{}\"]", name)).unwrap()).next()?.attr("value").map(String::from);
+        // Uten formet e brukernavnet eller passordet feil, eller så har feide endra innlogginga
+        match (form.and_then(|f| f.attr("action")), input("SAMLResponse"), input("RelayState")) {
+            (Some(action), Some(saml), Some(relay)) => (action.to_string(), saml, relay),
+            _ => return Err(unexpected("fant ikkje SAML formet, e passordet riktig?")),
+        }
+    };
 
     let _res4 = client.post(formAction)
         .header("Content-Type", "application/x-www-form-urlencoded") // Må sett denne for at servern ska les body
@@ -356,21 +551,23 @@ async fn newClient(secretStore: &SecretStore, pool: &sqlx::PgPool, userIndex: us
             urlencoding::encode(&SAMLResponse).into_owned(), 
             urlencoding::encode(&RelayState).into_owned()
         ))
-        .send().await.unwrap();
+        .sendRecorded().await?;
 
-    let cookieStr = cookie_store.clone().cookies(&Url::parse("https://tp.educloud.no").unwrap()).unwrap();
-    let cookieStr = cookieStr.to_str().unwrap().split(';').map(|e| e.trim()).filter(|e| e.starts_with("PHPSESSID=")).next().unwrap();
-    pool.set(&(KEY_TP_COOKIE_PREFIX.to_string() + &userIndex.to_string()), &cookieStr).await;
+    let cookieStr = fixtures::cookies(&cookie_store, &Url::parse(tp::baseUrl()).unwrap()).ok_or_else(|| unexpected("fikk ingen cookies"))?;
+    let cookieStr = cookieStr.to_str().unwrap_or("").split(';').map(|e| e.trim()).find(|e| e.starts_with("PHPSESSID="))
+        .ok_or_else(|| unexpected("fikk ingen PHPSESSID"))?;
+    sesjon::save(pool, &account.brukernavn, cookieStr).await;
 
     println!("Finished logging in client!");
-    client
+    Ok(client)
 }
 
 
 /// Skaffe en reqwest::Clent med cookies fra postgres 
-async fn getClient(pool: &sqlx::PgPool, userIndex: usize) -> Client {
+async fn getClient(pool: &sqlx::PgPool, account: &Account) -> Client {
     let cookieJar = Arc::new(cookie::Jar::default());
-    cookieJar.add_cookie_str(&pool.get(&(KEY_TP_COOKIE_PREFIX.to_string() + &userIndex.to_string())).await, &"https://tp.educloud.no".parse::<reqwest::Url>().unwrap());
+    let cookie = sesjon::load(pool, &account.brukernavn).await.map(|session| session.cookie).unwrap_or_default();
+    cookieJar.add_cookie_str(&cookie, &tp::baseUrl().parse::<reqwest::Url>().unwrap());
     Client::builder() // Den følge redirect by default
         .cookie_provider(cookieJar)
         .build()
@@ -378,90 +575,213 @@ async fn getClient(pool: &sqlx::PgPool, userIndex: usize) -> Client {
 }
 
 
+const KEY_TP_BOOKINGS_PREFIX: &str = "TP_BOOKINGS_KEY_";
+
 // Hjelpefunksjon som skaffe en client og et sett bookings
 // Dette fordi client validere cookie ved å send et request, og første request vi sende
 // i begge inngangan (nettsida og cronjob) e å skaff egne bookings
-async fn getClientAndBookings(secretStore: &SecretStore, pool: &sqlx::PgPool, userIndex: usize) -> (Client, Vec<Value>) {
-    let client = getClient(pool, userIndex).await;
-    let bookings = client.getBookings().await;
-    if bookings.is_ok() { // Om cookien e good
-        return (client, bookings.unwrap().as_array().unwrap().to_owned());
-    }
-
-    // Om cookien ikkje e det
-    let client = newClient(secretStore, pool, userIndex).await;
-    let bookings = client.getBookings().await.unwrap().as_array().unwrap().to_owned();
-    (client, bookings)
+// Nettsida bruke Login::Background, så da feile vi heller og lar sesjon::refreshSessions logge inn.
+async fn getClientAndBookings(account: &Account, policy: &BookingPolicy, pool: &sqlx::PgPool, login: Login) -> Result<(Client, Vec<TpBooking>), TpError> {
+    let client = getClient(pool, account).await;
+    let (client, bookings) = match client.getBookings(&policy.institusjon).await {
+        Ok(bookings) => {
+            // Om cookien e good
+            sesjon::markValidated(pool, &account.brukernavn).await;
+            (client, bookings)
+        },
+        // Om cookien ikkje e det. Et uventa svar kan også vær innloggingssida, så da prøve vi også å logg inn på nytt.
+        Err(e @ (TpError::AuthExpired | TpError::UnexpectedPayload(_))) if login == Login::Background => {
+            sesjon::requestRefresh();
+            return Err(e);
+        },
+        Err(TpError::AuthExpired | TpError::UnexpectedPayload(_)) => {
+            let client = newClient(account, policy, pool).await?;
+            let bookings = client.getBookings(&policy.institusjon).await?;
+            (client, bookings)
+        },
+        Err(e) => return Err(e),
+    };
+
+    // Husk bookingan, sånn at nettsida har nåkka å vis neste gang TP ikkje svare
+    pool.set(&(KEY_TP_BOOKINGS_PREFIX.to_string() + &account.brukernavn), &serde_json::to_string(&bookings).unwrap()).await;
+    Ok((client, bookings))
 }
 
+/// Bookingan te en konto sånn dem va sist getClientAndBookings fikk svar fra TP
+async fn cachedBookings(account: &Account, pool: &sqlx::PgPool) -> Vec<TpBooking> {
+    serde_json::from_str(&pool.get(&(KEY_TP_BOOKINGS_PREFIX.to_string() + &account.brukernavn)).await).unwrap_or_default()
+}
 
-// En for mazemap apien, en for sjølve mazemap lenka
-const MAZEMAP_API_PREFIX: &str = "https://search.mazemap.com/search/equery/?rows=1&start=0&withpois=true&campusid=597&z=1&q=";
-const MAZEMAP_PREFIX: &str = "https://use.mazemap.com/?utm_medium=shorturl&fromshortlink=true#v=1&campusid=21&sharepoitype=poi&sharepoi=";
 
 /// Fordi vi ønske å unngå å send MazeMap 10 requests hver load tar vi heller å lenke til
 /// en redirect lenke som redirecte til mazemap! Ganske fint system
-#[get("/mazemap/<room>")]
-async fn roomRedirect(room: &str) -> Redirect {
-    let json: Value = Client::new().get(format!("{}{}", MAZEMAP_API_PREFIX, room))
-        .send().await.unwrap().json().await.unwrap();
+/// Campusan kommer fra policyen te gruppa, en for mazemap apien og en for sjølve mazemap lenka.
+#[get("/g/<slug>/mazemap/<room>")]
+async fn roomRedirect(slug: &str, room: &str, groups: &State<Vec<Group>>) -> Option<Redirect> {
+    let policy = &gruppe::find(groups, slug)?.policy;
+    // Finn ikkje MazeMap rommet, f.eks. fordi campusen e feil, bli det en 404 i stedet for en panic
+    let json: Value = Client::new().get(format!("https://search.mazemap.com/search/equery/?rows=1&start=0&withpois=true&campusid={}&z=1&q={}", policy.mazemapCampus, room))
+        .sendRecorded().await.ok()?.json().await.ok()?;
 
-    let url = json.get("result").unwrap().get(0).unwrap().get("poiId").unwrap().as_i64().unwrap();
+    let url = json.get("result")?.get(0)?.get("poiId")?.as_i64()?;
 
-    Redirect::to(format!("{}{}", MAZEMAP_PREFIX, url.to_string()))
+    Some(Redirect::to(format!("https://use.mazemap.com/?utm_medium=shorturl&fromshortlink=true#v=1&campusid={}&sharepoitype=poi&sharepoi={}", policy.mazemapKartCampus, url)))
 }
 
 
-/// Hovedsida der man får en oversikt av bookingan
+/// Forsida. Med bare én gruppe går vi rett te sida dens, ellers får man velg gruppe.
 #[get("/")]
+fn groupList(groups: &State<Vec<Group>>) -> Either<Redirect, RawHtml<String>> {
+    if let [group] = groups.as_slice() {
+        return Either::Left(Redirect::to(uri!(index(&group.slug))));
+    }
+
+    Either::Right(page("Gruppene", html!{
+        @for group in groups.iter() {
+            div class="h-12 pt-3" { a href=(uri!(index(&group.slug))) { (group.policy.navn) } }
+        }
+    }))
+}
+
+
+/// Hovedsida te en gruppe der man får en oversikt av bookingan
+#[get("/g/<slug>")]
 async fn index(
-    secretStore: &State<SecretStore>,
+    slug: &str,
+    groups: &State<Vec<Group>>,
     pool: &State<sqlx::PgPool>
-) -> RawHtml<String> {
-    let mut bookings: Vec<Value> = Vec::new();
-
-    for clientIndex in 0..NUM_LOGINS {
-        let (_client, booking) = getClientAndBookings(&secretStore, &pool, clientIndex.into()).await;
-        bookings.extend(booking);
+) -> Option<RawHtml<String>> {
+    let group = gruppe::find(groups, slug)?;
+    let policy = &group.policy;
+    let mut bookings: Vec<TpBooking> = Vec::new();
+    let mut unreachable = false;
+
+    // Svare ikkje TP vise vi bookingan fra sist gang i stedet for å feile
+    for account in group.accounts.iter() {
+        match getClientAndBookings(account, policy, pool, Login::Background).await {
+            Ok((_client, booking)) => bookings.extend(booking),
+            Err(e) => {
+                println!("Fikk ikkje henta bookingan te {}: {}", account.brukernavn, e);
+                unreachable = true;
+                bookings.extend(cachedBookings(account, pool).await);
+            },
+        }
     }
 
-    let mut bookings: Vec<Value> = bookings.into_iter().filter(|e| e.get("name").unwrap() == BOOKING_NAVN).collect();
+    let mut bookings: Vec<TpBooking> = bookings.into_iter().filter(|e| policy.isOurs(&e.name)).collect();
+    let requests = bestilling::upcomingRequests(pool, slug, &planner::osloNaa()).await;
+
+    bookings.sort_by_key(|booking| booking.booked);
+
+    Some(page(&format!("{} timeplan!", policy.navn), html!{
+        @if unreachable {
+            div class="pt-3" { "Fikk ikkje kontakt med TP, så dette e bookingan fra sist gang." }
+        }
+        div class="flex flex-row justify-around h-12 pt-3 text-lg" {
+            span { "Rom" }
+            span { "Dato" }
+            span { "Klokkeslett" }
+        }
+        @for booking in &bookings { 
+            div class="flex flex-row justify-around h-12 pt-3" {
+                a href=(uri!(roomRedirect(slug, booking.room()))) { (booking.room()) }
+                span { (booking.firstday.format_localized("%A den %e.", Locale::nb_NO)) }
+                span { (booking.tid) }
+            }
+        }
+        @if !requests.is_empty() {
+            h2 class="text-lg pt-3" { "Bestilte ekstra økter" }
+            @for request in &requests {
+                div class="flex flex-row justify-around h-12 pt-3" {
+                    span { (request.start.date().format_localized("%A den %e.", Locale::nb_NO)) " " (request.start.format("%H:%M")) "-" (request.slutt.format("%H:%M")) }
+                    span { (request.room.as_deref().unwrap_or(&request.status)) }
+                }
+                @if request.room.is_none() && !request.detail.is_empty() {
+                    div class="truncate" { (request.detail) }
+                }
+            }
+        }
+        @if group.passkode.is_some() {
+            div class="pt-3" { a href=(uri!(bestilling::requestForm(slug))) { "Bestill en ekstra økt" } }
+        }
+        div class="pt-3" { a href=(uri!(ledig::freeRoomsPage(None::<&str>, None::<&str>))) { "Ledige rom nå" } }
+        div class="pb-12" { "
+Denne nettsiden bruke Jakob og Pål sin rombooking til å automatisk booke rom på " (policy.bygning) " til " (policy.navn) ".
+Alle rom-navnene lenker til mazemap:) " a href="https://github.com/JakobLien/HelgaSangern" { "(kildekode)" } 
+        }
+    }))
+}
+
+
+/// Viser hva neste cron-kjøring kommer te å gjør, uten å faktisk book nåkka
+#[get("/g/<slug>/plan")]
+async fn plan(
+    slug: &str,
+    groups: &State<Vec<Group>>,
+    pool: &State<sqlx::PgPool>
+) -> Option<RawHtml<String>> {
+    let group = gruppe::find(groups, slug)?;
+    let policy = &group.policy;
+    let plan = match makeBookingPlan(group, pool, ChronoDuration::minutes(30), Login::Background).await {
+        Ok((_clients, _quotas, plan)) => plan,
+        Err(e) => return Some(page("Neste bookinga", html!{
+            div class="pt-3 pb-12" { "Fikk ikkje kontakt med TP: " (e) }
+        })),
+    };
+
+    Some(page("Neste bookinga", html!{
+        div class="flex flex-row justify-around h-12 pt-3 text-lg" {
+            span { "Tid" }
+            span { "Rom" }
+            span { "Login" }
+        }
+        @for upgrade in &plan.upgrades {
+            div class="flex flex-row justify-around h-12 pt-3" {
+                span { (upgrade.booking.start.date().format_localized("%A %e.", Locale::nb_NO)) " " (upgrade.booking.start.format("%H:%M")) "-" (upgrade.booking.end.format("%H:%M")) }
+                span { "Bytte " (upgrade.booking.room) " mot " a href=(uri!(roomRedirect(slug, &upgrade.room))) { (upgrade.room) } }
+                span { (upgrade.clientIndex) }
+            }
+        }
+        @for slot in &plan.slots {
+            div class="flex flex-row justify-around h-12 pt-3" {
+                span { (slot.start.date().format_localized("%A %e.", Locale::nb_NO)) " " (slot.start.format("%H:%M")) "-" (slot.end.format("%H:%M")) @if slot.request.is_some() { " (ekstra)" } }
+                @if let Some(roomName) = &slot.room {
+                    a href=(uri!(roomRedirect(slug, roomName))) { (roomName) }
+                } @else {
+                    span { "Ingen ledig" }
+                }
+                span { (slot.clientIndex.map(|i| i.to_string()).unwrap_or("-".to_string())) }
+            }
+            @if let Some(room) = slot.room.as_deref().and_then(|r| policy.room(r)) {
+                div { (room.description()) }
+            }
+            @for rejected in &slot.rejected {
+                div class="truncate" { (rejected.room) ": " (rejected.reason) }
+            }
+        }
+        @if plan.quotaShortfall > 0 {
+            div class="pt-3" { "Kvoten e brukt opp, så " (plan.quotaShortfall) " økter blir ikkje booka." }
+        }
+        div class="pb-12" { "Dette e bare en plan, ingenting e booka." }
+    }))
+}
 
-    bookings.sort_by(|a, b| 
-        NaiveDateTime::parse_from_str(a.get("booked").unwrap().as_str().unwrap(), "%F %T").unwrap()
-        .partial_cmp(&NaiveDateTime::parse_from_str(b.get("booked").unwrap().as_str().unwrap(), "%F %T").unwrap()).unwrap()
-    );
 
+/// Felles ramme rundt alle sidene
+fn page(title: &str, content: Markup) -> RawHtml<String> {
     // Når du jobbe med dette kjør cargo watch -cqx 'shuttle run'
     // Når du jobbe med CSS kjør ./tailwindcss -o static/styles.css --watch --minify
     RawHtml(html!{
         (DOCTYPE)
         html {
             head {
-                link rel="stylesheet" href="static/styles.css"
+                link rel="stylesheet" href="/static/styles.css"
                 meta name="viewport" content="width=device-width, initial-scale=1.0" {}
             }
             body class="text-center bg-[#aaf]" {
                 div class="m-auto max-w-96" {
-                    h1 class="text-2xl mt-4" { "HelgaSangern timeplan!" }
-                    div class="flex flex-row justify-around h-12 pt-3 text-lg" {
-                        span { "Rom" }
-                        span { "Dato" }
-                        span { "Klokkeslett" }
-                    }
-                    @for booking in &bookings { 
-                        div class="flex flex-row justify-around h-12 pt-3" {
-                            a href=(uri!(roomRedirect(booking.get("rooms").unwrap().get(0).unwrap().get("name").unwrap().as_str().unwrap()))) 
-                                { (booking.get("rooms").unwrap().get(0).unwrap().get("name").unwrap().as_str().unwrap()) }
-                            @let bookingDay = NaiveDate::parse_from_str(booking.get("firstday").unwrap().as_str().unwrap(), "%F").unwrap();
-                            span { (bookingDay.format_localized("%A den %e.", Locale::nb_NO)) }
-                            span { (booking.get("tid").unwrap().as_str().unwrap()) }
-                        }
-                    }
-                    div class="pb-12" { "
-Denne nettsiden bruke Jakob og Pål sin rombooking til å automatisk booke rom på Helgasetr til TXS kollokvie.
-Alle rom-navnene lenker til mazemap:) " a href="https://github.com/JakobLien/HelgaSangern" { "(kildekode)" } 
-                    }
+                    h1 class="text-2xl mt-4" { (title) }
+                    (content)
                 }
             }
         }
@@ -478,21 +798,32 @@ async fn main(
 ) -> ShuttleRocket {
     sqlx::migrate!().run(&pool).await.expect("Migrations failed :( ");
 
-    let secreteStoreClone = secretStore.clone();
+    // Med TP_MOCK=true monteres mock TP på denne servern, se mocktp.rs. Da må TP_URL peke dit,
+    // f.eks. http://localhost:8000/mocktp, for vi vet ikkje hvilken port shuttle run gir oss.
+    // TP_URL kan også peke hvor som helst ellers, f.eks. en mock TP på en annen maskin.
+    let mockTp = secretStore.get("TP_MOCK").as_deref() == Some("true");
+    match secretStore.get("TP_URL") {
+        Some(url) => tp::setBaseUrl(&url),
+        None if mockTp => panic!("TP_MOCK=true trenger TP_URL, f.eks. http://localhost:8000{}", mocktp::MOCK_PATH),
+        None => {},
+    }
+
+    let groups = Group::loadAll(&pool, &secretStore).await.expect("Ugyldige grupper");
+
+    let groupsClone = groups.clone();
     let poolClone = pool.clone();
 
     // lmao, dette va my enklar enn det vi gjor på tracking helper tidligar haha
     // TODO: E veit ikkje om denne måten å hånter state på fungere, men det virke nå sånn?
     tokio::spawn(async move {
-        let mut interval = interval(TokioDuration::from_secs(4 * 60 * 60));
-        loop {
-            interval.tick().await;
-
-            // Your cron job logic here
-            println!("Running cron job");
+        bookingScheduler(&groupsClone, &poolClone).await;
+    });
 
-            bookRooms(&secreteStoreClone, &poolClone).await;
-        }
+    // Hold feide sessionan i live, sånn at nettsida aldri må vent på en innlogging
+    let groupsClone = groups.clone();
+    let poolClone = pool.clone();
+    tokio::spawn(async move {
+        sesjon::refreshSessions(&groupsClone, &poolClone).await;
     });
 
     let secreteStoreClone2 = secretStore.clone();
@@ -524,11 +855,55 @@ async fn main(
         }
     });
 
-    let rocket = rocket::build()
+    let mut rocket = rocket::build()
         .mount("/static", FileServer::from(relative!("static/")))
-        .mount("/", routes![index, roomRedirect])
+        .mount("/", routes![groupList, index, plan, roomRedirect])
+        .mount("/", admin::routes())
+        .mount("/", bestilling::routes())
+        .mount("/", ledig::routes())
         .manage(secretStore)
-        .manage(pool);
+        .manage(pool)
+        .manage(groups);
+
+    if mockTp {
+        println!("Bruke mock TP på {}", tp::baseUrl());
+        rocket = rocket.mount(mocktp::MOCK_PATH, mocktp::routes()).manage(mocktp::MockTp::default());
+    }
 
     Ok( rocket.into() )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::policy::TEST_POLICY;
+
+    /// Heile bookRooms mot opptaket i fixtures/test, det samme som fixtures::tests::planOgBookFraOpptak bruke.
+    /// Den treng en postgres i DATABASE_URL, så den kjøres bare med cargo test -- --ignored.
+    #[sqlx::test]
+    #[ignore = "treng postgres i DATABASE_URL"]
+    async fn bookRomFraOpptak(pool: sqlx::PgPool) {
+        fixtures::replayForTest(relative!("fixtures/test"));
+        let group = Group {
+            slug: "test".to_string(),
+            policy: BookingPolicy::parse(TEST_POLICY).unwrap(),
+            accounts: vec![Account { index: 0, brukernavn: "ola".to_string(), passord: "hemmelig".to_string(), kvote: 3 }],
+            passkode: None,
+        };
+
+        assert_eq!(bookRooms(&group, &pool).await, 0);
+
+        let attempts: Vec<(String, Option<NaiveDateTime>, Option<String>)> = sqlx::query_as(
+            "SELECT kind, slot_start, room FROM booking_attempts WHERE kind IN ('upgraded', 'booked', 'failed') ORDER BY id")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        let at = |t: &str| Some(NaiveDateTime::parse_from_str(t, "%F %H:%M").unwrap());
+        assert_eq!(attempts, [
+            ("upgraded".to_string(), at("2027-01-12 08:00"), Some("A".to_string())),
+            ("booked".to_string(), at("2027-01-13 08:00"), Some("B".to_string())),
+            ("booked".to_string(), at("2027-01-14 08:00"), Some("A".to_string())),
+        ]);
+        assert_eq!(fixtures::replayedRequests().iter().filter(|request| request.starts_with("POST")).count(), 3);
+    }
+}
@@ -0,0 +1,432 @@
+use std::sync::{Arc, Mutex};
+
+use chrono::{NaiveDate, NaiveDateTime};
+use rocket::{delete, form::{Form, FromForm}, get, http::{uri::Host, Cookie, CookieJar, Status}, post, response::{content::{RawHtml, RawJson}, Redirect}, routes, Route, State};
+use serde_json::json;
+
+use crate::gruppe::Group;
+use crate::planner;
+use crate::tp::{TpBooking, TpBookingRoom, TpReservationRequest, TpRoomEvent, TpRoomSchedule};
+
+// En liten TP som bare finnes i minnet, sånn at heile bookinga kan kjøres på en laptop uten å book ekte rom.
+// Skru den på med TP_MOCK=true i Secrets.toml, da monteres den på /mocktp. TP_URL må også peke dit,
+// med porten servern kjøre på, som TP_URL = "http://localhost:8000/mocktp".
+// Feide innlogginga e også med, men godtar alle passord. Brukernavnet bli PHPSESSID, sånn at hver konto har sine egne bookinga.
+
+pub const MOCK_PATH: &str = "/mocktp";
+
+/// Hvor mange bookinga hver bruker får ha samtidig, som hos ekte TP
+const MOCK_KVOTE: usize = 8;
+
+struct MockReservation {
+    id: u64,
+    user: String,
+    name: String,
+    room: String,
+    start: NaiveDateTime,
+    end: NaiveDateTime,
+}
+
+/// Alt mock TP husker. Forsvinn når servern restartes. Klona dele samme tilstand, sånn at testan kan se og endre den.
+#[derive(Default, Clone)]
+pub struct MockTp {
+    state: Arc<Mutex<(u64, Vec<MockReservation>)>>,
+    #[cfg(test)]
+    sabotage: Arc<Mutex<Sabotage>>,
+}
+
+/// Feil testan kan be mock TP om å lag, for å se at klientkoden tåle dem
+#[cfg(test)]
+#[derive(Default)]
+struct Sabotage {
+    /// reservation.php svare uten id, sånn som vi ikkje vet om ekte TP alltid gjør
+    utenId: bool,
+    /// Nån andre booke rommet med en gang vi avbestille det
+    stjelAvbestilte: bool,
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![loginStart, feideSelect, feideLoginPage, feideLogin, feideSaml, bookings, roomSchedule, roomCatalog, reserve, cancel]
+}
+
+/// Brukeren som e logga inn, eller Unauthorized sånn at klienten logge inn på nytt
+fn user(cookies: &CookieJar<'_>) -> Result<String, Status> {
+    cookies.get("PHPSESSID").map(|c| c.value().to_string()).ok_or(Status::Unauthorized)
+}
+
+/// Romman i ekte TP har byggkoden foran navnet, så vi fjerne byggkoden te den gruppa som har rommet
+fn roomName<'a>(id: &'a str, groups: &[Group]) -> &'a str {
+    groups.iter()
+        .find_map(|group| id.strip_prefix(group.policy.byggkode.as_str()).filter(|rom| group.policy.room(rom).is_some()))
+        .unwrap_or(id)
+}
+
+fn error(status: Status, msg: &str) -> (Status, RawJson<String>) {
+    (status, RawJson(json!({ "error": msg }).to_string()))
+}
+
+// Feide innlogginga, steg for steg sånn newClient går gjennom den
+
+/// newClient ber om /<institusjon>/?login=1, og <_tom..> e der for å matche den tomme biten etter /
+#[get("/<_institusjon>/<_tom..>?login=1")]
+fn loginStart(_institusjon: &str, _tom: std::path::PathBuf, host: &Host<'_>) -> Redirect {
+    let returnTo = format!("http://{}{}/feide/login?mock=1", host, MOCK_PATH);
+    Redirect::to(format!("{}/feide?returnTo={}", MOCK_PATH, urlencoding::encode(&returnTo)))
+}
+
+#[get("/feide")]
+fn feideSelect() -> RawHtml<&'static str> {
+    RawHtml("<p>Velg institusjon</p>")
+}
+
+#[get("/feide/login")]
+fn feideLoginPage() -> RawHtml<&'static str> {
+    RawHtml("<p>Logg inn med feide</p>")
+}
+
+#[derive(FromForm)]
+struct FeideForm {
+    feidename: String,
+}
+
+#[post("/feide/login", data = "<form>")]
+fn feideLogin(form: Form<FeideForm>, host: &Host<'_>) -> RawHtml<String> {
+    RawHtml(format!(r#"<form action="http://{}{}/feide/saml"><input name="SAMLResponse" value="{}"><input name="RelayState" value="mock"></form>"#,
+        host, MOCK_PATH, form.feidename))
+}
+
+#[derive(FromForm)]
+struct SamlForm {
+    #[field(name = "SAMLResponse")]
+    samlResponse: String,
+}
+
+#[post("/feide/saml", data = "<form>")]
+fn feideSaml(form: Form<SamlForm>, cookies: &CookieJar<'_>) -> &'static str {
+    cookies.add(Cookie::build(("PHPSESSID", form.samlResponse.clone())).path("/"));
+    "Logga inn"
+}
+
+// Selve TP apien
+
+#[get("/<_institusjon>/ws/rombestilling/bookings.php")]
+fn bookings(_institusjon: &str, cookies: &CookieJar<'_>, mock: &State<MockTp>) -> Result<RawJson<String>, Status> {
+    let user = user(cookies)?;
+    let state = mock.state.lock().unwrap();
+    let bookings: Vec<TpBooking> = state.1.iter()
+        .filter(|r| r.user == user)
+        .map(|r| TpBooking {
+            id: r.id.to_string(),
+            name: r.name.clone(),
+            booked: r.start,
+            firstday: r.start.date(),
+            tid: format!("{} - {}", r.start.format("%H:%M"), r.end.format("%H:%M")),
+            rooms: vec![TpBookingRoom { name: r.room.clone() }],
+        })
+        .collect();
+    Ok(RawJson(serde_json::to_string(&bookings).unwrap()))
+}
+
+#[get("/<_institusjon>/ws/1.4/room.php?<id>&<fromdate>&<todate>")]
+fn roomSchedule(_institusjon: &str, id: &str, fromdate: &str, todate: &str, groups: &State<Vec<Group>>, mock: &State<MockTp>) -> Result<RawJson<String>, (Status, RawJson<String>)> {
+    let date = |d: &str| NaiveDate::parse_from_str(d, "%F").map_err(|_| error(Status::BadRequest, "ugyldig dato"));
+    let (fromDate, toDate) = (date(fromdate)?, date(todate)?);
+    let room = roomName(id, groups);
+
+    let state = mock.state.lock().unwrap();
+    let events = state.1.iter()
+        .filter(|r| r.room == room && r.start.date() >= fromDate && r.start.date() <= toDate)
+        .map(|r| TpRoomEvent { summary: r.name.clone(), dtstart: planner::osloTilUtc(&r.start), dtend: planner::osloTilUtc(&r.end) })
+        .collect();
+    Ok(RawJson(serde_json::to_string(&TpRoomSchedule { events }).unwrap()))
+}
+
+/// Romkatalogen e alle romman i policyane te gruppene, så alt gruppene vil ha finnes
+#[get("/<_institusjon>/ws/rombestilling/rooms.php")]
+fn roomCatalog(_institusjon: &str, groups: &State<Vec<Group>>) -> RawJson<String> {
+    let rooms: Vec<_> = groups.iter()
+        .flat_map(|group| group.policy.rom.iter().map(move |room| json!({
+            "name": room.navn,
+            "building_name": group.policy.bygning,
+            "size": room.kapasitet,
+            "can_book": true,
+        })))
+        .collect();
+    RawJson(serde_json::to_string(&rooms).unwrap())
+}
+
+#[post("/<_institusjon>/ws/rombestilling/reservation.php", data = "<body>")]
+fn reserve(_institusjon: &str, body: &str, cookies: &CookieJar<'_>, groups: &State<Vec<Group>>, mock: &State<MockTp>) -> Result<RawJson<String>, (Status, RawJson<String>)> {
+    let user = user(cookies).map_err(|status| error(status, "ikkje logga inn"))?;
+    let request: TpReservationRequest = serde_json::from_str(body).map_err(|e| error(Status::BadRequest, &e.to_string()))?;
+    let [room] = request.rooms.as_slice() else {
+        return Err(error(Status::BadRequest, "book ett rom om gangen"));
+    };
+    let room = roomName(room, groups);
+    if request.start >= request.end {
+        return Err(error(Status::BadRequest, "start må vær før end"));
+    }
+
+    let mut state = mock.state.lock().unwrap();
+    if state.1.iter().filter(|r| r.user == user).count() >= MOCK_KVOTE {
+        return Err(error(Status::BadRequest, "Du har nådd kvoten for antall bookinger"));
+    }
+    if state.1.iter().any(|r| r.room == room && r.start < request.end && request.start < r.end) {
+        return Err(error(Status::Conflict, "Rommet er allerede opptatt"));
+    }
+
+    state.0 += 1;
+    let id = state.0;
+    state.1.push(MockReservation { id, user, name: request.name, room: room.to_string(), start: request.start, end: request.end });
+    println!("Mock TP booka {} {} - {}", room, request.start, request.end);
+    #[cfg(test)]
+    if mock.sabotage.lock().unwrap().utenId {
+        return Ok(RawJson("{}".to_string()));
+    }
+    Ok(RawJson(json!({ "id": id }).to_string()))
+}
+
+#[delete("/<_institusjon>/ws/rombestilling/reservation.php?<id>")]
+fn cancel(_institusjon: &str, id: u64, cookies: &CookieJar<'_>, mock: &State<MockTp>) -> Result<RawJson<String>, (Status, RawJson<String>)> {
+    let user = user(cookies).map_err(|status| error(status, "ikkje logga inn"))?;
+    let mut state = mock.state.lock().unwrap();
+    let index = state.1.iter().position(|r| r.id == id && r.user == user)
+        .ok_or_else(|| error(Status::NotFound, "fant ikkje reservasjonen"))?;
+    let _cancelled = state.1.remove(index);
+    #[cfg(test)]
+    if mock.sabotage.lock().unwrap().stjelAvbestilte {
+        state.0 += 1;
+        let id = state.0;
+        state.1.push(MockReservation { id, user: "nån andre".to_string(), name: "Nån andre".to_string(), .._cancelled });
+    }
+    Ok(RawJson(json!({ "id": id }).to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use rocket::{fairing::AdHoc, http::ContentType, local::asynchronous::Client};
+
+    use super::*;
+    use crate::policy::{BookingPolicy, TEST_POLICY};
+    use crate::tp::{self, ClientMethods, ReservationError, RoomId, TpError};
+
+    fn group() -> Group {
+        Group { slug: "test".to_string(), policy: BookingPolicy::parse(TEST_POLICY).unwrap(), accounts: Vec::new(), passkode: None }
+    }
+
+    async fn client() -> Client {
+        let rocket = rocket::build()
+            .mount(MOCK_PATH, routes())
+            .manage(MockTp::default())
+            .manage(vec![group()]);
+        Client::tracked(rocket).await.unwrap()
+    }
+
+    async fn json<T: serde::de::DeserializeOwned>(res: rocket::local::asynchronous::LocalResponse<'_>) -> T {
+        serde_json::from_str(&res.into_string().await.unwrap()).unwrap()
+    }
+
+    fn reservation(room: &str, start: &str, end: &str) -> String {
+        let time = |t: &str| NaiveDateTime::parse_from_str(t, "%F %H:%M").unwrap();
+        serde_json::to_string(&TpReservationRequest {
+            start: time(start),
+            end: time(end),
+            rooms: vec![room.to_string()],
+            name: "Test".to_string(),
+            notes: String::new(),
+            userGroup: None,
+        }).unwrap()
+    }
+
+    /// Samme steg som newClient, getBookings, bookRoom og cancelBooking, bare mot rocket direkte
+    #[rocket::async_test]
+    async fn loggInnBookOgAvbestill() {
+        let client = client().await;
+        let bookingsUrl = format!("{}/ntnu/ws/rombestilling/bookings.php", MOCK_PATH);
+        let reservationUrl = format!("{}/ntnu/ws/rombestilling/reservation.php", MOCK_PATH);
+        assert_eq!(client.get(&bookingsUrl).dispatch().await.status(), Status::Unauthorized);
+
+        // Feide innlogginga
+        // Mock TP lage absolutte adresser fra Host, sånn som newClient får dem fra feide
+        let host = Host::new(rocket::http::uri::Authority::parse("localhost:8000").unwrap());
+        let mut req = client.get(format!("{}/ntnu/?login=1", MOCK_PATH));
+        req.set_host(host.clone());
+        let res = req.dispatch().await;
+        assert!(res.headers().get_one("Location").unwrap().starts_with(&format!("{}/feide?returnTo=", MOCK_PATH)));
+        let mut req = client.post(format!("{}/feide/login", MOCK_PATH))
+            .header(ContentType::Form)
+            .body("has_js=0&feidename=ola&password=hva+som+helst");
+        req.set_host(host);
+        let res = req.dispatch().await;
+        assert!(res.into_string().await.unwrap().contains(r#"name="SAMLResponse" value="ola""#));
+        let res = client.post(format!("{}/feide/saml", MOCK_PATH))
+            .header(ContentType::Form)
+            .body("SAMLResponse=ola&RelayState=mock")
+            .dispatch().await;
+        assert_eq!(res.status(), Status::Ok);
+        assert_eq!(client.cookies().get("PHPSESSID").map(|c| c.value().to_string()).as_deref(), Some("ola"));
+
+        // Booking, og samme rom samtidig e opptatt
+        let res = client.post(&reservationUrl).body(reservation("250A", "2027-01-12 08:00", "2027-01-12 12:00")).dispatch().await;
+        assert_eq!(res.status(), Status::Ok);
+        let res = client.post(&reservationUrl).body(reservation("250A", "2027-01-12 10:00", "2027-01-12 14:00")).dispatch().await;
+        assert_eq!(res.status(), Status::Conflict);
+
+        let bookings: Vec<TpBooking> = json(client.get(&bookingsUrl).dispatch().await).await;
+        assert_eq!(bookings.len(), 1);
+        assert_eq!((bookings[0].room(), bookings[0].tid.as_str()), ("A", "08:00 - 12:00"));
+
+        let schedule: TpRoomSchedule = json(client.get(format!("{}/ntnu/ws/1.4/room.php?id=250A&fromdate=2027-01-11&todate=2027-01-25", MOCK_PATH))
+            .dispatch().await).await;
+        assert_eq!(schedule.events.len(), 1);
+
+        // Avbestilling
+        let res = client.delete(format!("{}?id={}", reservationUrl, bookings[0].id)).dispatch().await;
+        assert_eq!(res.status(), Status::Ok);
+        let bookings: Vec<TpBooking> = json(client.get(&bookingsUrl).dispatch().await).await;
+        assert!(bookings.is_empty());
+    }
+
+    // ClientMethods::modifyBooking mot mock TP over ekte HTTP, sånn som i produksjon
+
+    /// Start mock TP på en ledig port og pek TP adressen dit for resten av testen
+    async fn server() -> MockTp {
+        let mock = MockTp::default();
+        let (port, portRx) = tokio::sync::oneshot::channel();
+        let config = rocket::Config {
+            address: Ipv4Addr::LOCALHOST.into(),
+            port: 0,
+            log_level: rocket::config::LogLevel::Off,
+            ..rocket::Config::debug_default()
+        };
+        let rocket = rocket::custom(config)
+            .mount(MOCK_PATH, routes())
+            .manage(mock.clone())
+            .manage(vec![group()])
+            .attach(AdHoc::on_liftoff("Port", |rocket| Box::pin(async move {
+                let _ = port.send(rocket.config().port);
+            })));
+        tokio::spawn(rocket.launch());
+        tp::setBaseUrlForTest(&format!("http://127.0.0.1:{}{}", portRx.await.unwrap(), MOCK_PATH));
+        mock
+    }
+
+    /// En klient som e logga inn som user
+    fn tpClient(user: &str) -> reqwest::Client {
+        let jar = std::sync::Arc::new(reqwest::cookie::Jar::default());
+        jar.add_cookie_str(&format!("PHPSESSID={}", user), &tp::baseUrl().parse().unwrap());
+        reqwest::Client::builder().cookie_provider(jar).build().unwrap()
+    }
+
+    fn time(t: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(t, "%F %H:%M").unwrap()
+    }
+
+    fn room(navn: &str) -> RoomId {
+        group().policy.roomId(navn)
+    }
+
+    async fn book(client: &reqwest::Client, rom: &str, start: &str, end: &str) -> TpBooking {
+        let id = client.bookRoom("Test", &room(rom), &time(start), &time(end)).await.unwrap().id.unwrap();
+        client.getBookings("ntnu").await.unwrap().into_iter().find(|b| b.id == id).unwrap()
+    }
+
+    /// Rom og klokkeslett te bookingan te klienten, sortert
+    async fn bookings(client: &reqwest::Client) -> Vec<(String, String)> {
+        let mut bookings: Vec<_> = client.getBookings("ntnu").await.unwrap().iter()
+            .map(|b| (b.room().to_string(), format!("{} {}", b.firstday, b.tid)))
+            .collect();
+        bookings.sort();
+        bookings
+    }
+
+    fn owned(bookings: &[(&str, &str)]) -> Vec<(String, String)> {
+        bookings.iter().map(|(room, time)| (room.to_string(), time.to_string())).collect()
+    }
+
+    #[tokio::test]
+    async fn flyttTeAnnaRomOgSeinereISammeRom() {
+        server().await;
+        let ola = tpClient("ola");
+
+        let booking = book(&ola, "A", "2027-01-12 08:00", "2027-01-12 12:00").await;
+        ola.modifyBooking(&booking, &room("B"), &booking.booked, &booking.end().unwrap()).await.unwrap();
+        assert_eq!(bookings(&ola).await, owned(&[("B", "2027-01-12 08:00 - 12:00")]));
+
+        // Den nye overlappe den gamle i samme rom, så den gamle må avbestilles først
+        let booking = book(&ola, "A", "2027-01-13 08:00", "2027-01-13 12:00").await;
+        ola.modifyBooking(&booking, &room("A"), &time("2027-01-13 09:00"), &time("2027-01-13 13:00")).await.unwrap();
+        assert_eq!(bookings(&ola).await, owned(&[("A", "2027-01-13 09:00 - 13:00"), ("B", "2027-01-12 08:00 - 12:00")]));
+    }
+
+    #[tokio::test]
+    async fn avbestillIkkjeNårNånAndreHarRommet() {
+        server().await;
+        let (ola, kari) = (tpClient("ola"), tpClient("kari"));
+        book(&kari, "A", "2027-01-12 12:00", "2027-01-12 14:00").await;
+
+        let booking = book(&ola, "A", "2027-01-12 08:00", "2027-01-12 12:00").await;
+        let result = ola.modifyBooking(&booking, &room("A"), &time("2027-01-12 09:00"), &time("2027-01-12 13:00")).await;
+        assert!(matches!(result, Err(TpError::Refused(ReservationError::Conflict(_)))));
+        // Den gamle ble aldri avbestilt, så den har fortsatt samme id
+        let ids: Vec<String> = ola.getBookings("ntnu").await.unwrap().into_iter().map(|b| b.id).collect();
+        assert_eq!(ids, [booking.id]);
+    }
+
+    /// Ola har full kvote, så den gamle må avbestilles før den nye kan bookes
+    async fn fullKvote(ola: &reqwest::Client) -> TpBooking {
+        for day in 12..=(11 + MOCK_KVOTE) {
+            book(ola, "A", &format!("2027-01-{} 08:00", day), &format!("2027-01-{} 12:00", day)).await;
+        }
+        ola.getBookings("ntnu").await.unwrap().into_iter().find(|b| b.firstday.format("%d").to_string() == "12").unwrap()
+    }
+
+    #[tokio::test]
+    async fn flyttMedFullKvote() {
+        server().await;
+        let ola = tpClient("ola");
+        let booking = fullKvote(&ola).await;
+
+        ola.modifyBooking(&booking, &room("B"), &booking.booked, &booking.end().unwrap()).await.unwrap();
+        let bookings = bookings(&ola).await;
+        assert_eq!(bookings.len(), MOCK_KVOTE);
+        assert!(bookings.contains(&("B".to_string(), "2027-01-12 08:00 - 12:00".to_string())));
+    }
+
+    #[tokio::test]
+    async fn denGamleBookesPåNyttNårDenNyeFeile() {
+        let mock = server().await;
+        let (ola, kari) = (tpClient("ola"), tpClient("kari"));
+        book(&kari, "B", "2027-01-12 08:00", "2027-01-12 12:00").await;
+        let booking = fullKvote(&ola).await;
+        let before = bookings(&ola).await;
+
+        // B e opptatt, så ola får A tebake
+        let result = ola.modifyBooking(&booking, &room("B"), &booking.booked, &booking.end().unwrap()).await;
+        assert!(matches!(result, Err(TpError::Refused(ReservationError::Conflict(_)))));
+        assert_eq!(bookings(&ola).await, before);
+
+        // Men tar nån andre A mens den e avbestilt, har ola mista den
+        let booking = ola.getBookings("ntnu").await.unwrap().into_iter().find(|b| b.firstday == booking.firstday).unwrap();
+        mock.sabotage.lock().unwrap().stjelAvbestilte = true;
+        let result = ola.modifyBooking(&booking, &room("B"), &booking.booked, &booking.end().unwrap()).await;
+        assert!(matches!(result, Err(TpError::BookingLost(..))));
+        assert_eq!(bookings(&ola).await.len(), MOCK_KVOTE - 1);
+    }
+
+    #[tokio::test]
+    async fn denNyeAvbestillesUtenIdFraTp() {
+        let mock = server().await;
+        let ola = tpClient("ola");
+        let booking = book(&ola, "A", "2027-01-12 08:00", "2027-01-12 12:00").await;
+        mock.sabotage.lock().unwrap().utenId = true;
+
+        // Den gamle finnes ikkje, så den får vi ikkje avbestilt, og da må den nye avbestilles igjen
+        let missing = TpBooking { id: "999".to_string(), ..booking };
+        let result = ola.modifyBooking(&missing, &room("B"), &missing.booked, &missing.end().unwrap()).await;
+        assert!(result.is_err());
+        assert_eq!(bookings(&ola).await, owned(&[("A", "2027-01-12 08:00 - 12:00")]));
+    }
+}
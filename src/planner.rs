@@ -0,0 +1,570 @@
+use std::{cmp::Reverse, collections::{HashMap, HashSet}};
+
+use chrono::{DateTime, Days, Duration as ChronoDuration, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Europe::Oslo;
+
+use crate::bestilling::BookingRequest;
+use crate::fixtures;
+use crate::policy::BookingPolicy;
+use crate::rom::Room;
+use crate::tp::TpRoomEvent;
+
+/// Hvorfor et rom ikkje ble valgt for en booking
+#[derive(Debug, Clone)]
+pub enum RejectReason {
+    /// Rommet har en event som overlappe med tidsrommet vi vil book
+    Occupied { summary: String, start: DateTime<Utc>, end: DateTime<Utc> },
+    /// Rommet har færre plassa enn policy.minKapasitet
+    TooSmall { capacity: u16 },
+}
+
+impl std::fmt::Display for RejectReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RejectReason::Occupied { summary, start, end } => write!(f, "opptatt av {} {}-{}",
+                summary, start.with_timezone(&Oslo).format("%e. %H:%M"), end.with_timezone(&Oslo).format("%e. %H:%M")),
+            RejectReason::TooSmall { capacity } => write!(f, "for lite, bare {} plassa", capacity),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RejectedRoom {
+    pub room: String,
+    pub reason: RejectReason,
+}
+
+/// En booking planleggern har bestemt seg for. room og clientIndex e None om ingen rom va ledig.
+/// alternatives e dem andre ledige romman i prioritert rekkefølge, i tilfelle TP ikkje vil gi oss room.
+/// request e id-en te bestillinga om økta kom fra bestillingsskjemaet i stedet for fra policyen.
+#[derive(Debug, Clone)]
+pub struct PlannedSlot {
+    pub start: NaiveDateTime,
+    pub end: NaiveDateTime,
+    pub request: Option<i64>,
+    pub room: Option<String>,
+    pub clientIndex: Option<usize>,
+    pub alternatives: Vec<String>,
+    pub rejected: Vec<RejectedRoom>,
+}
+
+/// En booking vi allerede har i TP
+#[derive(Debug, Clone)]
+pub struct ExistingBooking {
+    pub id: String,
+    pub clientIndex: usize,
+    pub start: NaiveDateTime,
+    pub end: NaiveDateTime,
+    pub room: String,
+}
+
+/// Et bytte fra en booking vi har te et bedre rom som har blitt ledig i samme tidsrom.
+/// clientIndex e loginen som booke det nye rommet, booking.clientIndex avbestille det gamle.
+#[derive(Debug, Clone)]
+pub struct PlannedUpgrade {
+    pub booking: ExistingBooking,
+    pub room: String,
+    pub clientIndex: usize,
+}
+
+/// quotaShortfall e hvor mange fleire økter vi ville booka om kvoten ikkje va brukt opp,
+/// f.eks. fordi nån har booka ting manuelt på en av loginan.
+#[derive(Debug, Clone, Default)]
+pub struct BookingPlan {
+    pub upgrades: Vec<PlannedUpgrade>,
+    pub slots: Vec<PlannedSlot>,
+    pub quotaShortfall: usize,
+}
+
+/// Hvor mange bookinga en login har, og hvor mange TP lar den ha
+#[derive(Debug, Clone, Copy)]
+pub struct Quota {
+    pub used: u8,
+    pub max: u8,
+}
+
+impl Quota {
+    pub fn free(&self) -> u8 {
+        self.max.saturating_sub(self.used)
+    }
+}
+
+/// Loginen med færrest bookinga som fortsatt har ledig kvote, sånn at bookingan fordeles jevnt.
+/// None om alle e fulle.
+pub fn pickClient(quotas: &[Quota]) -> Option<usize> {
+    (0..quotas.len())
+        .filter(|i| quotas[*i].free() > 0)
+        .min_by_key(|i| quotas[*i].used)
+}
+
+/// Gitt en datetime generere denne start og slutt på neste booking vi ønske:)
+/// Det e neste økt samme dag som starte etter dateTime om den finnes, ellers første økt neste dag med økter.
+/// Helligdager og blackout perioder fra policyen hoppes over.
+pub fn getNextBooking(
+    policy: &BookingPolicy,
+    dateTime: &NaiveDateTime
+) -> (NaiveDateTime, NaiveDateTime) {
+    let mut date = dateTime.date();
+    loop {
+        if policy.isBookingDay(&date) {
+            if let Some(session) = policy.sessionsOn(&date).into_iter().find(|(start, _)| start > dateTime) {
+                return session;
+            }
+        }
+        date = date.checked_add_days(Days::new(1)).unwrap();
+    }
+}
+
+/// Når TP neste gang åpne en ny dag for booking. TP lar oss book policy.horisontDager fram i tid,
+/// og en ny dag åpne klokka policy.horisontApner norsk tid. Åpninga av dager vi ikkje booke hoppes over.
+pub fn nextHorizonOpening(policy: &BookingPolicy, now: &DateTime<Utc>) -> DateTime<Utc> {
+    let mut date = now.with_timezone(&Oslo).date_naive();
+    loop {
+        let opening = osloTilUtc(&date.and_time(policy.horisontApner));
+        let openedDay = date.checked_add_days(Days::new(policy.horisontDager.into())).unwrap();
+        if opening > *now && policy.isBookingDay(&openedDay) {
+            return opening;
+        }
+        date = date.checked_add_days(Days::new(1)).unwrap();
+    }
+}
+
+/// Klokka i Norge akkurat nå. Øktene og horisonten e i norsk tid, så det e denne planleggern må få som now.
+/// Den går etter fixtures::now(), så når et opptak spilles av e det klokka fra opptaket.
+pub fn osloNaa() -> NaiveDateTime {
+    fixtures::now().with_timezone(&Oslo).naive_local()
+}
+
+/// Gjør en lokal (norsk) tid om til UTC, sånn at vi kan sammenlign med tidspunkta fra TP.
+/// None om klokkeslettet ikkje finnes fordi klokka stilles fram, som 02:30 siste søndag i mars.
+/// Når klokka stilles tebake brukes den første av dem to.
+pub fn tryOsloTilUtc(dateTime: &NaiveDateTime) -> Option<DateTime<Utc>> {
+    Oslo.from_local_datetime(dateTime).earliest().map(|time| time.with_timezone(&Utc))
+}
+
+/// Som tryOsloTilUtc, men et klokkeslett som ikkje finnes flyttes en time fram sammen med klokka,
+/// sånn at ei økt fra 02:30 den natta starte 03:30.
+pub fn osloTilUtc(dateTime: &NaiveDateTime) -> DateTime<Utc> {
+    tryOsloTilUtc(dateTime)
+        .or_else(|| tryOsloTilUtc(&(*dateTime + ChronoDuration::hours(1))))
+        .unwrap()
+}
+
+/// Sjekke om to tidsrom overlappe. Slutten e ikkje med, så en forelesning som slutte 13:00
+/// hindre ikkje en booking som starte 13:00.
+pub fn overlapper(start1: &DateTime<Utc>, end1: &DateTime<Utc>, start2: &DateTime<Utc>, end2: &DateTime<Utc>) -> bool {
+    start1 < end2 && start2 < end1
+}
+
+/// Tidsrommet en booking tar i UTC
+fn bookingWindow(start: &NaiveDateTime, end: &NaiveDateTime) -> (DateTime<Utc>, DateTime<Utc>) {
+    (osloTilUtc(start), osloTilUtc(end))
+}
+
+/// Finn første event i rommet som kolliderer med tidsrommet, om det finnes en.
+/// Våre egne bookinga (med policy.navn) teller ikkje.
+fn findCollision(policy: &BookingPolicy, roomSchedule: &[TpRoomEvent], start: &DateTime<Utc>, end: &DateTime<Utc>) -> Option<RejectReason> {
+    for event in roomSchedule {
+        if event.summary != policy.navn && overlapper(&event.dtstart, &event.dtend, start, end) {
+            return Some(RejectReason::Occupied { summary: event.summary.clone(), start: event.dtstart, end: event.dtend });
+        }
+    }
+    None
+}
+
+/// Bookinga som starte innen så lenge byttes ikkje, for da sitte vi kanskje allerede i rommet
+const UPGRADE_MARGIN: ChronoDuration = ChronoDuration::minutes(30);
+
+/// Finn framtidige bookinga der et strengt bedre rom (høyere vekt, eller lik vekt og før i policyen) e ledig i samme tidsrom.
+/// Det nye rommet må bookes før det gamle avbestilles, så det trengs en login med ledig kvote.
+/// now e norsk tid, og bookinga som har starta eller starte innen UPGRADE_MARGIN røres ikkje.
+fn planUpgrades(
+    policy: &BookingPolicy,
+    existingBookings: &[ExistingBooking],
+    quotas: &mut [Quota],
+    roomSchedules: &HashMap<String, Vec<TpRoomEvent>>,
+    cancelledDays: &HashSet<NaiveDate>,
+    now: &NaiveDateTime
+) -> Vec<PlannedUpgrade> {
+    // Romman fra best te dårligst, uten bonus for forrige rom
+    let mut ranking: Vec<&Room> = policy.rom.iter().filter(|room| room.kapasitet >= policy.minKapasitet).collect();
+    ranking.sort_by_key(|room| Reverse(room.vekt));
+
+    // Våre egne bookinga vises ikkje som opptatt i timeplanen, så hold styr på dem sjøl
+    let mut taken: Taken = existingBookings.iter()
+        .map(|booking| {
+            let (start, end) = bookingWindow(&booking.start, &booking.end);
+            (start, end, booking.room.clone())
+        })
+        .collect();
+
+    let mut upgrades = Vec::new();
+    for booking in existingBookings.iter().filter(|b| b.start > *now + UPGRADE_MARGIN && !cancelledDays.contains(&b.start.date())) {
+        let currentRank = ranking.iter().position(|room| room.navn == booking.room).unwrap_or(ranking.len());
+        let (start, end) = bookingWindow(&booking.start, &booking.end);
+
+        let better = ranking[..currentRank].iter().find(|room| {
+            let roomSchedule = roomSchedules.get(&room.navn).map(|s| s.as_slice()).unwrap_or(&[]);
+            findCollision(policy, roomSchedule, &start, &end).is_none()
+                && !taken.iter().any(|(takenStart, takenEnd, takenRoom)| *takenRoom == room.navn && overlapper(takenStart, takenEnd, &start, &end))
+        });
+        let Some(better) = better else { continue };
+
+        // Bruk helst samme login, sånn at bookingan hold seg der dem va
+        let clientIndex = if quotas[booking.clientIndex].free() > 0 {
+            Some(booking.clientIndex)
+        } else {
+            pickClient(quotas)
+        };
+        let Some(clientIndex) = clientIndex else { continue };
+
+        quotas[clientIndex].used += 1;
+        quotas[booking.clientIndex].used -= 1;
+        taken.push((start, end, better.navn.clone()));
+        upgrades.push(PlannedUpgrade { booking: booking.clone(), room: better.navn.clone(), clientIndex });
+    }
+    upgrades
+}
+
+// Tidsrom vi allerede har planlagt eller booka et rom i, i UTC
+type Taken = Vec<(DateTime<Utc>, DateTime<Utc>, String)>;
+
+/// Finn alle ledige rom for et tidsrom, best først, og hvorfor dem andre ikkje e ledige.
+/// Rom med færre enn minKapasitet plassa e for små, og rom i taken e allerede planlagt av oss.
+/// previousRoom e rommet vi hadde økta før, og det får policy.forrigeRomBonus.
+fn rankRooms(
+    policy: &BookingPolicy,
+    roomSchedules: &HashMap<String, Vec<TpRoomEvent>>,
+    start: &NaiveDateTime,
+    end: &NaiveDateTime,
+    minKapasitet: u16,
+    previousRoom: Option<&str>,
+    taken: &Taken
+) -> (Vec<String>, Vec<RejectedRoom>) {
+    let (bookingStart, bookingEnd) = bookingWindow(start, end);
+
+    let mut rejected: Vec<RejectedRoom> = Vec::new();
+    let mut freeRooms: Vec<&Room> = Vec::new();
+    for room in policy.rom.iter() {
+        if room.kapasitet < minKapasitet {
+            rejected.push(RejectedRoom { room: room.navn.clone(), reason: RejectReason::TooSmall { capacity: room.kapasitet } });
+            continue;
+        }
+
+        let roomSchedule = roomSchedules.get(&room.navn).map(|s| s.as_slice()).unwrap_or(&[]);
+
+        // Sjekk om nån events i rommet overlappe med tidsrommet vi vil book. Isåfall, continue.
+        // Dette funke også for events som går over flere dager.
+        if let Some(reason) = findCollision(policy, roomSchedule, &bookingStart, &bookingEnd) {
+            rejected.push(RejectedRoom { room: room.navn.clone(), reason });
+            continue;
+        }
+
+        // Våre egne bookinga vises ikkje som opptatt i timeplanen, så dem sjekkes her
+        if let Some((takenStart, takenEnd, _)) = taken.iter().find(|(takenStart, takenEnd, takenRoom)| *takenRoom == room.navn && overlapper(takenStart, takenEnd, &bookingStart, &bookingEnd)) {
+            rejected.push(RejectedRoom { room: room.navn.clone(), reason: RejectReason::Occupied { summary: policy.navn.clone(), start: *takenStart, end: *takenEnd } });
+            continue;
+        }
+
+        freeRooms.push(room);
+    }
+
+    // sort_by_key e stabil, så ved lik score vinne det som står først i policyen
+    freeRooms.sort_by_key(|room| Reverse(room.score(previousRoom, policy.forrigeRomBonus)));
+    (freeRooms.into_iter().map(|room| room.navn.clone()).collect(), rejected)
+}
+
+/// Bestemme hvilke tidspunkt, rom og logins vi ska book, uten å faktisk book nåkka.
+/// Først ser vi om nån av bookingan vi har kan byttes te bedre rom, så tar vi bestillingan fra skjemaet,
+/// og til slutt planlegg vi nye bookinga fra policyen med kvoten som e igjen.
+/// existingBookings e bookingan vi allerede har, quotas e kvoten te hver login,
+/// roomSchedules e eventsa til hvert rom i policy.rom, cancelledDays e dager vi ikkje ska book,
+/// og requests e bestillingan som venter.
+pub fn planBookings(
+    policy: &BookingPolicy,
+    existingBookings: &[ExistingBooking],
+    quotas: &[Quota],
+    roomSchedules: &HashMap<String, Vec<TpRoomEvent>>,
+    cancelledDays: &HashSet<NaiveDate>,
+    requests: &[BookingRequest],
+    now: &NaiveDateTime
+) -> BookingPlan {
+    let mut quotas = quotas.to_vec();
+    let mut plan = BookingPlan {
+        upgrades: planUpgrades(policy, existingBookings, &mut quotas, roomSchedules, cancelledDays, now),
+        slots: Vec::new(),
+        quotaShortfall: 0,
+    };
+
+    // Starttid og rom for alle bookingan, etter byttan
+    let mut bookings: Vec<(NaiveDateTime, String)> = Vec::new();
+    let mut taken: Taken = Vec::new();
+    for booking in existingBookings {
+        let upgrade = plan.upgrades.iter().find(|u| u.booking.id == booking.id);
+        let room = upgrade.map(|u| u.room.clone()).unwrap_or(booking.room.clone());
+        let (start, end) = bookingWindow(&booking.start, &booking.end);
+        bookings.push((booking.start, room.clone()));
+        taken.push((start, end, room));
+    }
+
+    // TP lar oss ikkje book lenger fram enn horisonten, så det som ligg lenger fram tar vi neste gang
+    let lastDay = now.date().checked_add_days(Days::new(policy.horisontDager.into())).unwrap();
+
+    // Bestillingan fra skjemaet får rom før dem vanlige øktene
+    for request in requests.iter().filter(|r| r.start > *now && r.start.date() <= lastDay) {
+        let minKapasitet = u16::try_from(request.min_kapasitet).unwrap_or(u16::MAX).max(policy.minKapasitet);
+        let (freeRooms, rejected) = rankRooms(policy, roomSchedules, &request.start, &request.slutt, minKapasitet, None, &taken);
+        let mut slot = PlannedSlot { start: request.start, end: request.slutt, request: Some(request.id), room: None, clientIndex: None, alternatives: Vec::new(), rejected };
+
+        let mut freeRooms = freeRooms.into_iter();
+        if let (Some(roomName), Some(clientIndex)) = (freeRooms.next(), pickClient(&quotas)) {
+            quotas[clientIndex].used += 1;
+            let (start, end) = bookingWindow(&request.start, &request.slutt);
+            taken.push((start, end, roomName.clone()));
+            slot.room = Some(roomName);
+            slot.clientIndex = Some(clientIndex);
+            slot.alternatives = freeRooms.collect();
+        }
+        plan.slots.push(slot);
+    }
+
+    // Vi vil ha like mange økter som loginan til sammen har kvote te, men kan bare book så mange som e ledig.
+    // Manuelle bookinga og bestillinga på loginan tar av kvoten uten å telle som økter.
+    let wanted = quotas.iter().map(|q| usize::from(q.max)).sum::<usize>().saturating_sub(existingBookings.len());
+    let freeQuota: usize = quotas.iter().map(|q| usize::from(q.free())).sum();
+    plan.quotaShortfall = wanted.saturating_sub(freeQuota);
+
+    let getNextBooking = |dateTime: &NaiveDateTime| {
+        let mut next = getNextBooking(policy, dateTime);
+        while cancelledDays.contains(&next.0.date()) {
+            next = getNextBooking(policy, &next.0);
+        }
+        next
+    };
+    let nextInsideHorizon = |dateTime: &NaiveDateTime| Some(getNextBooking(dateTime)).filter(|(start, _)| start.date() <= lastDay);
+
+    // Generer liste av ting vi ønske å booke, som start og slutt. Nye økter starte etter den siste vi har,
+    // men aldri før now, ellers ville vi prøvd å book ei økt som allerede har starta.
+    let lastStart = bookings.iter().map(|(time, _)| *time).max().unwrap_or(*now).max(*now);
+    let mut newBookingTimes: Vec<(NaiveDateTime, NaiveDateTime)> = Vec::new();
+    for _i in 0..wanted.min(freeQuota) {
+        let Some(next) = nextInsideHorizon(&newBookingTimes.last().map(|(start, _)| *start).unwrap_or(lastStart)) else { break };
+        newBookingTimes.push(next);
+    }
+
+    // Må bruk while i stedet for en for loop fordi størrelsen endre seg ila loopinga. E funksjonelt sett en for loop:)
+    let mut newBookingTimesIndex = 0;
+    while newBookingTimesIndex < newBookingTimes.len() {
+        let (newBookingTime, newBookingEnd) = newBookingTimes[newBookingTimesIndex];
+
+        // Rommet vi hadde økta før denne får en bonus
+        let previousRoom = bookings.iter()
+            .filter(|(time, _)| *time < newBookingTime)
+            .max_by_key(|(time, _)| *time)
+            .map(|(_, room)| room.clone());
+        let (freeRooms, rejected) = rankRooms(policy, roomSchedules, &newBookingTime, &newBookingEnd, policy.minKapasitet, previousRoom.as_deref(), &taken);
+        let mut slot = PlannedSlot { start: newBookingTime, end: newBookingEnd, request: None, room: None, clientIndex: None, alternatives: Vec::new(), rejected };
+
+        let mut freeRooms = freeRooms.into_iter();
+        if let Some(roomName) = freeRooms.next() {
+            // Logikk som velge ledig client
+            let Some(clientIndex) = pickClient(&quotas) else {
+                // Alle loginan e fulle, så resten av øktene får vi ikkje booka denne gangen
+                plan.quotaShortfall += newBookingTimes.len() - newBookingTimesIndex;
+                break;
+            };
+
+            quotas[clientIndex].used += 1;
+            bookings.push((newBookingTime, roomName.clone()));
+            let (start, end) = bookingWindow(&newBookingTime, &newBookingEnd);
+            taken.push((start, end, roomName.clone()));
+            slot.room = Some(roomName);
+            slot.clientIndex = Some(clientIndex);
+            slot.alternatives = freeRooms.collect();
+        }
+
+        if slot.room.is_none() {
+            // Om du ikkje kunna book en booking
+            if let Some(next) = nextInsideHorizon(&newBookingTimes.last().unwrap().0) {
+                newBookingTimes.push(next);
+            }
+        }
+
+        plan.slots.push(slot);
+        newBookingTimesIndex += 1;
+    }
+
+    plan
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::policy::TEST_POLICY;
+
+    fn policy() -> BookingPolicy {
+        BookingPolicy::parse(TEST_POLICY).unwrap()
+    }
+
+    fn at(dateTime: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(dateTime, "%F %H:%M").unwrap()
+    }
+
+    #[test]
+    fn horisontenRegnesFraNorskDato() {
+        // Rett etter midnatt i Norge e det fortsatt dagen før i UTC, men TP har åpna mandagen 14 dager fram
+        let now = at("2027-01-11 00:01");
+        let quotas = [Quota { used: 0, max: 20 }];
+        let plan = planBookings(&policy(), &[], &quotas, &HashMap::new(), &HashSet::new(), &[], &now);
+
+        let newDay = NaiveDate::from_ymd_opt(2027, 1, 25).unwrap();
+        assert!(plan.slots.iter().any(|slot| slot.start.date() == newDay && slot.room.is_some()));
+        assert!(plan.slots.iter().all(|slot| slot.start.date() <= newDay));
+    }
+
+    #[test]
+    fn horisontenÅpneNesteBookingdag() {
+        let policy = policy();
+        let utc = |t: &str| at(t).and_utc();
+        // Mandag åpne tirsdagen to uker fram ved midnatt norsk tid
+        assert_eq!(nextHorizonOpening(&policy, &utc("2027-01-11 11:00")), utc("2027-01-11 23:00"));
+        // Akkurat når den åpne e det neste som gjelder
+        assert_eq!(nextHorizonOpening(&policy, &utc("2027-01-11 23:00")), utc("2027-01-12 23:00"));
+        // Fredag hoppe over lørdagen og søndagen som åpne, og neste e mandagen
+        assert_eq!(nextHorizonOpening(&policy, &utc("2027-01-15 11:00")), utc("2027-01-17 23:00"));
+        // 17. mai bookes ikkje, så den åpninga hoppes også over, og på sommeren e midnatt 22:00 UTC
+        assert_eq!(nextHorizonOpening(&policy, &utc("2027-05-02 10:00")), utc("2027-05-03 22:00"));
+
+        let morgen = BookingPolicy::parse(&TEST_POLICY.replace("horisontApner = \"00:00\"", "horisontApner = \"07:00\"")).unwrap();
+        assert_eq!(nextHorizonOpening(&morgen, &utc("2027-01-11 05:00")), utc("2027-01-11 06:00"));
+    }
+
+    #[test]
+    fn klokkaStillesFram() {
+        let gap = at("2027-03-28 02:30");
+        assert_eq!(tryOsloTilUtc(&gap), None);
+        assert_eq!(osloTilUtc(&gap), tryOsloTilUtc(&at("2027-03-28 03:30")).unwrap());
+        assert_eq!(osloTilUtc(&at("2027-03-28 01:30")).format("%H:%M").to_string(), "00:30");
+    }
+
+    fn existing(id: &str, start: &str, end: &str, room: &str) -> ExistingBooking {
+        ExistingBooking { id: id.to_string(), clientIndex: 0, start: at(start), end: at(end), room: room.to_string() }
+    }
+
+    #[test]
+    fn byttIkkjeRommetViSitteI() {
+        let bookings = [
+            existing("1", "2027-01-11 08:00", "2027-01-11 12:00", "B"),
+            existing("2", "2027-01-12 08:00", "2027-01-12 12:00", "B"),
+        ];
+        let mut quotas = [Quota { used: 2, max: 8 }];
+        let upgrades = planUpgrades(&policy(), &bookings, &mut quotas, &HashMap::new(), &HashSet::new(), &at("2027-01-11 09:30"));
+
+        assert_eq!(upgrades.len(), 1);
+        assert_eq!(upgrades[0].booking.id, "2");
+        assert_eq!(upgrades[0].room, "A");
+
+        // Økta som starte om et kvarter e også for seint å bytte
+        let upgrades = planUpgrades(&policy(), &bookings, &mut quotas, &HashMap::new(), &HashSet::new(), &at("2027-01-12 07:45"));
+        assert!(upgrades.is_empty());
+    }
+
+    #[test]
+    fn nyeØkterStarteIkkjeFørNow() {
+        // To økter om dagen, og vi har hatt formiddagen i dag
+        let policy = BookingPolicy::parse(&TEST_POLICY.replace("[[rom]]", "[[okt]]
+    ukedager = [0, 1, 2, 3, 4]
+    start = \"13:00\"
+    slutt = \"17:00\"
+
+    [[rom]]")).unwrap();
+        let bookings = [existing("1", "2027-01-11 08:00", "2027-01-11 12:00", "A")];
+        let quotas = [Quota { used: 1, max: 3 }];
+        let plan = planBookings(&policy, &bookings, &quotas, &HashMap::new(), &HashSet::new(), &[], &at("2027-01-11 14:00"));
+
+        assert_eq!(plan.slots[0].start, at("2027-01-12 08:00"));
+        assert!(plan.slots.iter().all(|slot| slot.start > at("2027-01-11 14:00")));
+    }
+
+    fn event(summary: &str, start: &str, end: &str) -> TpRoomEvent {
+        TpRoomEvent { summary: summary.to_string(), dtstart: osloTilUtc(&at(start)), dtend: osloTilUtc(&at(end)) }
+    }
+
+    #[test]
+    fn tidsromSomBareMøtesOverlapperIkkje() {
+        let (a, b, c, d) = (osloTilUtc(&at("2027-01-11 12:00")), osloTilUtc(&at("2027-01-11 13:00")),
+            osloTilUtc(&at("2027-01-11 13:01")), osloTilUtc(&at("2027-01-11 14:00")));
+        assert!(!overlapper(&a, &b, &b, &d));
+        assert!(!overlapper(&b, &d, &a, &b));
+        assert!(overlapper(&a, &c, &b, &d));
+        assert!(overlapper(&a, &d, &b, &c));
+    }
+
+    #[test]
+    fn nesteBookingHopperOverHelga() {
+        let policy = policy();
+        assert_eq!(getNextBooking(&policy, &at("2027-01-11 07:00")), (at("2027-01-11 08:00"), at("2027-01-11 12:00")));
+        assert_eq!(getNextBooking(&policy, &at("2027-01-15 08:00")), (at("2027-01-18 08:00"), at("2027-01-18 12:00")));
+    }
+
+    #[test]
+    fn opptatteOgForSmåRomRangeresIkkje() {
+        let policy = policy();
+        let schedules = HashMap::from([("A".to_string(), vec![event("Forelesning", "2027-01-11 11:00", "2027-01-11 13:00")])]);
+
+        let (free, rejected) = rankRooms(&policy, &schedules, &at("2027-01-11 08:00"), &at("2027-01-11 12:00"), 10, None, &Vec::new());
+        assert_eq!(free, ["B"]);
+        assert!(matches!(&rejected[0], RejectedRoom { room, reason: RejectReason::Occupied { .. } } if room == "A"));
+
+        // Forelesninga slutte 13:00, så ei økt fra 13:00 får A
+        let (free, _) = rankRooms(&policy, &schedules, &at("2027-01-11 13:00"), &at("2027-01-11 17:00"), 10, None, &Vec::new());
+        assert_eq!(free, ["A", "B"]);
+
+        let (free, rejected) = rankRooms(&policy, &schedules, &at("2027-01-12 08:00"), &at("2027-01-12 12:00"), 25, None, &Vec::new());
+        assert!(free.is_empty());
+        assert!(rejected.iter().all(|r| matches!(r.reason, RejectReason::TooSmall { capacity: 20 })));
+    }
+
+    #[test]
+    fn forrigeRomBonusKanSlåVekta() {
+        let mut policy = policy();
+        policy.forrigeRomBonus = 60;
+        let (free, _) = rankRooms(&policy, &HashMap::new(), &at("2027-01-11 08:00"), &at("2027-01-11 12:00"), 10, Some("B"), &Vec::new());
+        assert_eq!(free, ["B", "A"]);
+    }
+
+    #[test]
+    fn kvotenBlirBruktOpp() {
+        // To av tre plassa på loginen e tatt av manuelle bookinga, så vi får bare booka én av øktene
+        let quotas = [Quota { used: 2, max: 3 }];
+        let plan = planBookings(&policy(), &[], &quotas, &HashMap::new(), &HashSet::new(), &[], &at("2027-01-11 07:00"));
+        assert_eq!(plan.slots.len(), 1);
+        assert_eq!(plan.slots[0].clientIndex, Some(0));
+        assert_eq!(plan.quotaShortfall, 2);
+
+        assert_eq!(pickClient(&[Quota { used: 3, max: 3 }, Quota { used: 8, max: 8 }]), None);
+        assert_eq!(pickClient(&[Quota { used: 3, max: 8 }, Quota { used: 1, max: 8 }]), Some(1));
+    }
+
+    #[test]
+    fn byttBareTeStrengtBedreRom() {
+        let mut quotas = [Quota { used: 1, max: 8 }];
+        let none = HashMap::new();
+        let now = at("2027-01-11 07:00");
+
+        // A e allerede best
+        let inA = [existing("1", "2027-01-12 08:00", "2027-01-12 12:00", "A")];
+        assert!(planUpgrades(&policy(), &inA, &mut quotas, &none, &HashSet::new(), &now).is_empty());
+
+        // A e bedre, men opptatt
+        let inB = [existing("1", "2027-01-12 08:00", "2027-01-12 12:00", "B")];
+        let busy = HashMap::from([("A".to_string(), vec![event("Forelesning", "2027-01-12 10:00", "2027-01-12 11:00")])]);
+        assert!(planUpgrades(&policy(), &inB, &mut quotas, &busy, &HashSet::new(), &now).is_empty());
+
+        // Med lik vekt e rommet som står først i policyen bedre, men ikkje omvendt
+        let mut equal = policy();
+        equal.rom[1].vekt = equal.rom[0].vekt;
+        assert_eq!(planUpgrades(&equal, &inB, &mut quotas, &none, &HashSet::new(), &now)[0].room, "A");
+        assert!(planUpgrades(&equal, &inA, &mut quotas, &none, &HashSet::new(), &now).is_empty());
+    }
+}
@@ -0,0 +1,337 @@
+use std::collections::HashSet;
+
+use chrono::{Datelike, Duration as ChronoDuration, NaiveDate, NaiveDateTime, NaiveTime};
+use serde::Deserialize;
+
+use crate::GetSet;
+use crate::helligdager;
+use crate::rom::{CatalogRoom, Room};
+use crate::tp::RoomId;
+
+// Policyen te en gruppe leses fra databasen om den e satt der (BOOKING_POLICY_<slug>), ellers fra fila te gruppa.
+// Sånn kan vi endre romrekkefølge osv uten å deploy på nytt, bare ved å oppdater key_value tabellen.
+const KEY_BOOKING_POLICY_PREFIX: &str = "BOOKING_POLICY_";
+
+/// Alt som bestemme hva, når og hvor vi booke. Se booking.toml for forklaring av feltan.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BookingPolicy {
+    pub navn: String,
+    #[serde(default = "defaultInstitusjon")]
+    pub institusjon: String,
+    #[serde(default)]
+    pub feideRealm: Option<String>,
+    pub bygning: String,
+    #[serde(default = "defaultByggkode")]
+    pub byggkode: String,
+    #[serde(default = "defaultMazemapCampus")]
+    pub mazemapCampus: u32,
+    #[serde(default = "defaultMazemapKartCampus")]
+    pub mazemapKartCampus: u32,
+    pub okt: Vec<Okt>,
+    pub horisontDager: u32,
+    pub horisontApner: NaiveTime,
+    #[serde(default = "defaultHorisontForsok")]
+    pub horisontForsok: u32,
+    #[serde(default = "defaultHorisontPause")]
+    pub horisontPause: u64,
+    pub bookingsPerLogin: u8,
+    pub minKapasitet: u16,
+    #[serde(default)]
+    pub forrigeRomBonus: i32,
+    #[serde(default = "defaultHelligdager")]
+    pub helligdager: bool,
+    #[serde(default)]
+    pub blackout: Vec<Blackout>,
+    pub rom: Vec<Room>,
+}
+
+/// En liten policy med to rom, A e bedre enn B, og ei økt 08-12 på hverdager. Brukes av testan.
+#[cfg(test)]
+pub const TEST_POLICY: &str = r#"
+    navn = "Test"
+    bygning = "Testbygget"
+    horisontDager = 14
+    horisontApner = "00:00"
+    bookingsPerLogin = 3
+    minKapasitet = 10
+
+    [[okt]]
+    ukedager = [0, 1, 2, 3, 4]
+    start = "08:00"
+    slutt = "12:00"
+
+    [[rom]]
+    navn = "A"
+    kapasitet = 20
+    type = "grupperom"
+    vekt = 100
+
+    [[rom]]
+    navn = "B"
+    kapasitet = 20
+    type = "grupperom"
+    vekt = 50
+"#;
+
+fn defaultHelligdager() -> bool { true }
+// Helgasetr på NTNU, som va det eneste bygget før disse kunne settes
+fn defaultInstitusjon() -> String { "ntnu".to_string() }
+fn defaultByggkode() -> String { "250".to_string() }
+fn defaultMazemapCampus() -> u32 { 597 }
+fn defaultMazemapKartCampus() -> u32 { 21 }
+fn defaultHorisontForsok() -> u32 { 5 }
+fn defaultHorisontPause() -> u64 { 20 }
+
+/// Et tidsrom vi booke på gitte ukedager, fra start te slutt. Med blokk deles tidsrommet opp i bookinga
+/// på blokk timer hver, der den siste kan bli kortere. Med fra og/eller til gjelde økta bare i den perioden,
+/// og på dager der en sånn økt gjelde erstatte den dem vanlige øktene, f.eks. i eksamensperioden.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Okt {
+    pub ukedager: Vec<u8>,
+    pub start: NaiveTime,
+    pub slutt: NaiveTime,
+    pub blokk: Option<i64>,
+    pub fra: Option<NaiveDate>,
+    pub til: Option<NaiveDate>,
+}
+
+impl Okt {
+    fn periodisk(&self) -> bool {
+        self.fra.is_some() || self.til.is_some()
+    }
+
+    fn gjelder(&self, date: &NaiveDate) -> bool {
+        self.ukedager.contains(&(date.weekday().num_days_from_monday() as u8))
+            && self.fra.is_none_or(|fra| fra <= *date)
+            && self.til.is_none_or(|til| *date <= til)
+    }
+
+    /// Start og slutt på bookingan denne økta gir en dag
+    fn sessions(&self, date: &NaiveDate) -> Vec<(NaiveDateTime, NaiveDateTime)> {
+        let slutt = date.and_time(self.slutt);
+        let Some(blokk) = self.blokk else { return vec![(date.and_time(self.start), slutt)] };
+
+        let mut sessions = Vec::new();
+        let mut start = date.and_time(self.start);
+        while start < slutt {
+            let end = (start + ChronoDuration::hours(blokk)).min(slutt);
+            sessions.push((start, end));
+            start = end;
+        }
+        sessions
+    }
+}
+
+/// En periode vi ikkje booke rom, f.eks. juleferie eller eksamensfri. Både fra og til e med.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Blackout {
+    pub fra: NaiveDate,
+    pub til: NaiveDate,
+    pub navn: Option<String>,
+}
+
+impl BookingPolicy {
+    /// Les inn policyen te gruppa slug fra databasen, eller fra fil (relativt te prosjektmappa) om den ikkje ligg i databasen
+    pub async fn load(pool: &sqlx::PgPool, slug: &str, fil: &str) -> Result<BookingPolicy, String> {
+        let mut policyStr = pool.get(&(KEY_BOOKING_POLICY_PREFIX.to_string() + slug)).await;
+        if policyStr.is_empty() {
+            let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join(fil);
+            policyStr = std::fs::read_to_string(&path)
+                .map_err(|e| format!("Fikk ikkje lest {}: {}", path.display(), e))?;
+        }
+        BookingPolicy::parse(&policyStr)
+    }
+
+    pub fn parse(policyStr: &str) -> Result<BookingPolicy, String> {
+        let mut policy: BookingPolicy = toml::from_str(policyStr).map_err(|e| e.to_string())?;
+        policy.validate()?;
+        Ok(policy)
+    }
+
+    /// Sjekke at policyen gir mening, og sortere ukedagan
+    fn validate(&mut self) -> Result<(), String> {
+        if self.navn.trim().is_empty() {
+            return Err("navn kan ikkje vær tomt".to_string());
+        }
+        if self.institusjon.is_empty() || !self.institusjon.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+            return Err(format!("ugyldig institusjon {}, det e den delen av TP adressen som kommer etter tp.educloud.no/", self.institusjon));
+        }
+        if self.byggkode.is_empty() || !self.byggkode.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(format!("ugyldig byggkode {}", self.byggkode));
+        }
+        // Uten ei vanlig økt finnes det dager uten bookinga for alltid, og da finn vi aldri neste booking
+        if !self.okt.iter().any(|okt| !okt.periodisk()) {
+            return Err("det må vær minst ei økt uten fra og til".to_string());
+        }
+        for okt in self.okt.iter_mut() {
+            if okt.ukedager.is_empty() || okt.ukedager.iter().any(|d| *d > 6) {
+                return Err("ukedager må vær en ikkje-tom liste med tall fra 0 (mandag) til 6 (søndag)".to_string());
+            }
+            if okt.start >= okt.slutt {
+                return Err(format!("økta som starte {} må slutt etter den starte", okt.start));
+            }
+            if okt.blokk.is_some_and(|blokk| blokk < 1) {
+                return Err(format!("blokk i økta som starte {} må vær minst 1 time", okt.start));
+            }
+            if let (Some(fra), Some(til)) = (okt.fra, okt.til) {
+                if fra > til {
+                    return Err(format!("økta som starte {} har fra {} etter til {}", okt.start, fra, til));
+                }
+            }
+            okt.ukedager.sort();
+            okt.ukedager.dedup();
+        }
+        if self.horisontDager == 0 || self.horisontDager > 365 {
+            return Err("horisontDager må vær mellom 1 og 365".to_string());
+        }
+        if self.horisontPause == 0 {
+            return Err("horisontPause må vær minst 1 sekund".to_string());
+        }
+        if self.bookingsPerLogin == 0 {
+            return Err("bookingsPerLogin må vær minst 1".to_string());
+        }
+        if self.rom.is_empty() {
+            return Err("det må vær minst ett rom".to_string());
+        }
+
+        if let Some(blackout) = self.blackout.iter().find(|b| b.fra > b.til) {
+            return Err(format!("blackout fra {} e etter til {}", blackout.fra, blackout.til));
+        }
+
+        let mut sett = HashSet::new();
+        if let Some(rom) = self.rom.iter().find(|r| !sett.insert(&r.navn)) {
+            return Err(format!("{} e med flere gang i rom", rom.navn));
+        }
+
+        Ok(())
+    }
+
+    /// Start og slutt på alle bookingan vi vil ha en dag, sortert etter start. Helligdager og blackout sjekkes ikkje her.
+    pub fn sessionsOn(&self, date: &NaiveDate) -> Vec<(NaiveDateTime, NaiveDateTime)> {
+        let okter: Vec<&Okt> = self.okt.iter().filter(|okt| okt.gjelder(date)).collect();
+        let periodisk = okter.iter().any(|okt| okt.periodisk());
+
+        let mut sessions: Vec<(NaiveDateTime, NaiveDateTime)> = okter.into_iter()
+            .filter(|okt| okt.periodisk() == periodisk)
+            .flat_map(|okt| okt.sessions(date))
+            .collect();
+        sessions.sort();
+        sessions.dedup();
+        sessions
+    }
+
+    /// Navnet ekstra økter fra bestillingsskjemaet bookes med. Dem må hete nåkka anna enn dem vanlige,
+    /// ellers ville planleggern trudd dem va faste økter og begynt å plan etter dem.
+    pub fn extraNavn(&self) -> String {
+        format!("{} ekstra", self.navn)
+    }
+
+    /// Om en booking i TP e vår, enten en fast økt eller en ekstra
+    pub fn isOurs(&self, name: &str) -> bool {
+        name == self.navn || name == self.extraNavn()
+    }
+
+    /// Om vi vil ha nån bookinga en dato i det heile tatt
+    pub fn isBookingDay(&self, date: &NaiveDate) -> bool {
+        self.blackoutReason(date).is_none() && !self.sessionsOn(date).is_empty()
+    }
+
+    /// Hvorfor vi ikkje booke en dato, om den e en helligdag eller i en blackout periode
+    pub fn blackoutReason(&self, date: &NaiveDate) -> Option<String> {
+        if self.helligdager {
+            if let Some(name) = helligdager::holidayName(date) {
+                return Some(name.to_string());
+            }
+        }
+        self.blackout.iter()
+            .find(|b| b.fra <= *date && *date <= b.til)
+            .map(|b| b.navn.clone().unwrap_or("Blackout".to_string()))
+    }
+
+    /// Legg til romman fra katalogen som ikkje allerede står i policyen, sånn at nye bookbare rom bli funne automatisk
+    pub fn withCatalog(&self, catalog: &[CatalogRoom]) -> BookingPolicy {
+        let mut policy = self.clone();
+        for catalogRoom in catalog {
+            if policy.room(&catalogRoom.name).is_none() {
+                policy.rom.push(catalogRoom.toRoom());
+            }
+        }
+        policy
+    }
+
+    /// Rommet sånn TP kjenne det igjen, med institusjonen og byggkoden te policyen
+    pub fn roomId(&self, navn: &str) -> RoomId {
+        RoomId { institusjon: self.institusjon.clone(), bygg: self.byggkode.clone(), rom: navn.to_string() }
+    }
+
+    /// Feide realmen brukeran logge inn med, som default institusjon.no
+    pub fn feideRealm(&self) -> String {
+        self.feideRealm.clone().unwrap_or_else(|| format!("{}.no", self.institusjon))
+    }
+
+    pub fn room(&self, navn: &str) -> Option<&Room> {
+        self.rom.iter().find(|r| r.navn == navn)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(dateTime: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(dateTime, "%F %H:%M").unwrap()
+    }
+
+    fn okt(start: &str, slutt: &str, blokk: Option<i64>) -> Okt {
+        let time = |t: &str| NaiveTime::parse_from_str(t, "%H:%M").unwrap();
+        Okt { ukedager: vec![0, 1, 2, 3, 4, 5, 6], start: time(start), slutt: time(slutt), blokk, fra: None, til: None }
+    }
+
+    #[test]
+    fn sisteBlokkaBlirKortere() {
+        let date = NaiveDate::from_ymd_opt(2027, 1, 11).unwrap();
+        assert_eq!(okt("08:00", "18:00", Some(4)).sessions(&date), [
+            (at("2027-01-11 08:00"), at("2027-01-11 12:00")),
+            (at("2027-01-11 12:00"), at("2027-01-11 16:00")),
+            (at("2027-01-11 16:00"), at("2027-01-11 18:00")),
+        ]);
+        assert_eq!(okt("08:00", "16:00", Some(4)).sessions(&date).len(), 2);
+        assert_eq!(okt("08:00", "18:00", None).sessions(&date), [(at("2027-01-11 08:00"), at("2027-01-11 18:00"))]);
+    }
+
+    #[test]
+    fn periodiskeØkterErstatterDemVanlige() {
+        let mut policy = BookingPolicy::parse(TEST_POLICY).unwrap();
+        let mut eksamen = okt("10:00", "20:00", Some(5));
+        eksamen.fra = NaiveDate::from_ymd_opt(2027, 1, 13);
+        eksamen.til = NaiveDate::from_ymd_opt(2027, 1, 14);
+        policy.okt.push(eksamen);
+
+        assert_eq!(policy.sessionsOn(&NaiveDate::from_ymd_opt(2027, 1, 12).unwrap()), [(at("2027-01-12 08:00"), at("2027-01-12 12:00"))]);
+        assert_eq!(policy.sessionsOn(&NaiveDate::from_ymd_opt(2027, 1, 13).unwrap()), [
+            (at("2027-01-13 10:00"), at("2027-01-13 15:00")),
+            (at("2027-01-13 15:00"), at("2027-01-13 20:00")),
+        ]);
+        // Lørdagen etter eksamensperioden har ingen økter
+        assert!(!policy.isBookingDay(&NaiveDate::from_ymd_opt(2027, 1, 16).unwrap()));
+    }
+
+    #[test]
+    fn helligdagerBookesIkkje() {
+        let mut policy = BookingPolicy::parse(TEST_POLICY).unwrap();
+        let grunnlovsdag = NaiveDate::from_ymd_opt(2027, 5, 17).unwrap();
+        assert!(!policy.isBookingDay(&grunnlovsdag));
+        policy.helligdager = false;
+        assert!(policy.isBookingDay(&grunnlovsdag));
+    }
+
+    #[test]
+    fn policyUtenVanligeØkterEUgyldig() {
+        let policy = TEST_POLICY.replace("[[okt]]", "[[okt]]\n    fra = \"2027-01-01\"");
+        assert!(BookingPolicy::parse(&policy).is_err());
+        assert!(BookingPolicy::parse(&TEST_POLICY.replace("navn = \"Test\"", "navn = \"Test\"\n    byggkode = \"2 5\"")).is_err());
+    }
+}
@@ -0,0 +1,222 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::Deserialize;
+use serde_json::Value;
+use sqlx::types::Json;
+
+use crate::policy::BookingPolicy;
+use crate::tp::TpRoomEvent;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RoomType {
+    Grupperom,
+    Undervisningsrom,
+    Annet,
+}
+
+impl std::fmt::Display for RoomType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RoomType::Grupperom => write!(f, "grupperom"),
+            RoomType::Undervisningsrom => write!(f, "undervisningsrom"),
+            RoomType::Annet => write!(f, "annet rom"),
+        }
+    }
+}
+
+/// Alt vi veit om et rom. vekt e hvor gjerne vi vil ha rommet, høyest vinne.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Room {
+    pub navn: String,
+    pub kapasitet: u16,
+    #[serde(rename = "type")]
+    pub romtype: RoomType,
+    pub etasje: Option<i8>,
+    #[serde(default)]
+    pub fasiliteter: Vec<String>,
+    #[serde(default)]
+    pub vekt: i32,
+    pub notat: Option<String>,
+}
+
+impl Room {
+    /// Etasjen står i policyen om den e satt der, ellers e den første del av romnavnet (04-072 e i 4. etasje)
+    pub fn floor(&self) -> Option<i8> {
+        self.etasje.or_else(|| self.navn.split('-').next()?.parse().ok())
+    }
+
+    /// Kort beskrivelse av rommet, f.eks. "30 plassa, grupperom, 4. etasje, utsikt (bra rom)"
+    pub fn description(&self) -> String {
+        let mut parts = vec![format!("{} plassa", self.kapasitet), self.romtype.to_string()];
+        if let Some(floor) = self.floor() {
+            parts.push(format!("{}. etasje", floor));
+        }
+        parts.extend(self.fasiliteter.iter().cloned());
+
+        match &self.notat {
+            Some(notat) => format!("{} ({})", parts.join(", "), notat),
+            None => parts.join(", "),
+        }
+    }
+
+    /// Hvor godt vi lika rommet for en booking. previousRoom e rommet vi hadde forrige økt,
+    /// og det får en bonus sånn at vi slepp å flytt oss rundt hele tida.
+    pub fn score(&self, previousRoom: Option<&str>, previousRoomBonus: i32) -> i32 {
+        let mut score = self.vekt;
+        if previousRoom == Some(self.navn.as_str()) {
+            score += previousRoomBonus;
+        }
+        score
+    }
+}
+
+/// Et rom slik TP beskriv det i romsøket
+#[derive(Debug, Clone, Deserialize, sqlx::FromRow)]
+pub struct CatalogRoom {
+    pub name: String,
+    pub building_name: String,
+    pub size: i32,
+    pub can_book: bool,
+}
+
+impl CatalogRoom {
+    /// Plukk ut romman vi kan bruk fra svaret til TP: riktig bygning, bookbare og store nok
+    pub fn fromTp(json: &Value, policy: &BookingPolicy) -> Vec<CatalogRoom> {
+        let rooms = json.as_array().or_else(|| json.get("rooms")?.as_array()).cloned().unwrap_or_default();
+
+        rooms.into_iter()
+            .filter_map(|room| serde_json::from_value::<CatalogRoom>(room).ok())
+            .filter(|room| room.building_name == policy.bygning)
+            .filter(|room| room.can_book)
+            .filter(|room| room.size >= i32::from(policy.minKapasitet))
+            .collect()
+    }
+
+    /// Et rom fra katalogen som ikkje står i policyen. Det får vekt 0, så det bli bare valgt når dem andre e opptatt.
+    pub fn toRoom(&self) -> Room {
+        Room {
+            navn: self.name.clone(),
+            kapasitet: self.size.try_into().unwrap_or(0),
+            romtype: RoomType::Annet,
+            etasje: None,
+            fasiliteter: Vec::new(),
+            vekt: 0,
+            notat: Some("fra romkatalogen".to_string()),
+        }
+    }
+}
+
+/// Erstatt romkatalogen te bygget i policyen i databasen med en ny en. Andre bygg, som andre grupper bruke, får vær.
+/// Et rom e institusjon + byggkode + navn, som RoomId.
+pub async fn saveCatalog(pool: &sqlx::PgPool, policy: &BookingPolicy, rooms: &[CatalogRoom]) -> Result<(), sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    sqlx::query("DELETE FROM rooms WHERE institusjon = $1 AND byggkode = $2")
+        .bind(&policy.institusjon)
+        .bind(&policy.byggkode)
+        .execute(&mut *transaction)
+        .await?;
+    for room in rooms {
+        sqlx::query("INSERT INTO rooms (institusjon, byggkode, name, building_name, size, can_book) VALUES ($1, $2, $3, $4, $5, $6)")
+            .bind(&policy.institusjon)
+            .bind(&policy.byggkode)
+            .bind(&room.name)
+            .bind(&room.building_name)
+            .bind(room.size)
+            .bind(room.can_book)
+            .execute(&mut *transaction)
+            .await?;
+    }
+    transaction.commit().await
+}
+
+/// Skaffe romkatalogen te bygget i policyen sånn den va sist vi henta den fra TP
+pub async fn loadCatalog(pool: &sqlx::PgPool, policy: &BookingPolicy) -> Vec<CatalogRoom> {
+    sqlx::query_as("SELECT name, building_name, size, can_book FROM rooms WHERE institusjon = $1 AND byggkode = $2 ORDER BY size DESC, name")
+        .bind(&policy.institusjon)
+        .bind(&policy.byggkode)
+        .fetch_all(pool)
+        .await
+        .unwrap()
+}
+
+/// Timeplanen te et rom sånn den va da vi henta den fra TP, for dagan fra og med from_date te og med to_date
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct CachedSchedule {
+    /// RoomId-en te rommet, som ntnu/250/R1
+    pub room: String,
+    pub events: Json<Vec<TpRoomEvent>>,
+    pub from_date: NaiveDate,
+    pub to_date: NaiveDate,
+    pub fetched_at: DateTime<Utc>,
+}
+
+impl CachedSchedule {
+    /// Om timeplanen e nyere enn maxAge og dekke heile perioden vi treng
+    pub fn isFresh(&self, fromDate: NaiveDate, toDate: NaiveDate, maxAge: chrono::Duration) -> bool {
+        self.fetched_at > Utc::now() - maxAge && self.from_date <= fromDate && toDate <= self.to_date
+    }
+}
+
+/// Lagre en nyhenta timeplan, og erstatt den gamle for rommet
+pub async fn saveSchedule(pool: &sqlx::PgPool, room: &str, events: &[TpRoomEvent], fromDate: NaiveDate, toDate: NaiveDate) -> Result<(), sqlx::Error> {
+    sqlx::query("INSERT INTO room_schedules (room, events, from_date, to_date, fetched_at) VALUES ($1, $2, $3, $4, now())
+        ON CONFLICT (room) DO UPDATE SET events = $2, from_date = $3, to_date = $4, fetched_at = now()")
+        .bind(room)
+        .bind(Json(events))
+        .bind(fromDate)
+        .bind(toDate)
+        .execute(pool)
+        .await
+        .map(|_| ())
+}
+
+/// Legg en booking vi nettopp har gjort te i den lagra timeplanen te rommet, sånn at andre grupper ser
+/// at rommet e opptatt uten å måtte hent timeplanen fra TP på nytt
+pub async fn addToSchedule(pool: &sqlx::PgPool, room: &str, event: TpRoomEvent) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE room_schedules SET events = events || $2 WHERE room = $1")
+        .bind(room)
+        .bind(Json(vec![event]))
+        .execute(pool)
+        .await
+        .map(|_| ())
+}
+
+/// Skaffe alle timeplanan vi har lagra, uansett hvor gamle dem e
+pub async fn loadSchedules(pool: &sqlx::PgPool) -> Vec<CachedSchedule> {
+    sqlx::query_as("SELECT room, events, from_date, to_date, fetched_at FROM room_schedules")
+        .fetch_all(pool)
+        .await
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::policy::TEST_POLICY;
+
+    fn room(name: &str, building: &str, size: i32, canBook: bool) -> Value {
+        json!({ "name": name, "building_name": building, "size": size, "can_book": canBook, "floor": 4 })
+    }
+
+    #[test]
+    fn bareBookbareRomIBygget() {
+        let policy = BookingPolicy::parse(TEST_POLICY).unwrap();
+        let rooms = json!([
+            room("04-072", "Testbygget", 30, true),
+            room("04-073", "Et anna bygg", 30, true),
+            room("04-074", "Testbygget", 30, false),
+            room("04-075", "Testbygget", 9, true),
+            room("04-076", "Testbygget", 10, true),
+            { "name": "Mangle størrelse", "building_name": "Testbygget", "can_book": true },
+        ]);
+        let names: Vec<String> = CatalogRoom::fromTp(&rooms, &policy).into_iter().map(|room| room.name).collect();
+        assert_eq!(names, ["04-072", "04-076"]);
+
+        // TP pakke av og te lista inn i et objekt
+        assert_eq!(CatalogRoom::fromTp(&json!({ "rooms": rooms }), &policy).len(), 2);
+        assert!(CatalogRoom::fromTp(&json!({ "error": "ikkje logga inn" }), &policy).is_empty());
+    }
+}
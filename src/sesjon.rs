@@ -0,0 +1,103 @@
+use std::{collections::HashSet, sync::OnceLock};
+
+use chrono::{DateTime, Duration, Utc};
+use tokio::sync::Notify;
+
+use crate::gruppe::Group;
+use crate::{getClientAndBookings, newClient};
+
+// Cookien fra feide innlogginga te hver konto lagres i tp_sessions, sammen med når vi fikk den og når TP sist godtok den.
+// TP sine sessions dør når dem ikkje brukes på en stund, og feide innlogginga tar flere sekund.
+// Så refreshSessions bruke sessionan jevnlig, og logge inn på nytt før dem bli for gamle, sånn at nettsida aldri må vent på feide.
+
+/// Hvor ofte vi sjekke at sessionan fortsatt virke. Må vær godt under tida TP lar en ubrukt session leve.
+const SESSION_CHECK_INTERVAL: Duration = Duration::minutes(10);
+
+/// Sessions som e eldre enn dette logge vi inn på nytt, før dem går ut av seg sjøl
+const SESSION_MAX_AGE: Duration = Duration::hours(6);
+
+/// Om getClientAndBookings får logge inn med feide når sessionen e død
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Login {
+    /// Logg inn med en gang, det bookingkjøringan gjør
+    Inline,
+    /// Feil med en gang og la refreshSessions logge inn, det nettsida gjør
+    Background,
+}
+
+/// PHPSESSID cookien te en konto
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct Session {
+    pub cookie: String,
+    pub obtained_at: DateTime<Utc>,
+    pub validated_at: DateTime<Utc>,
+}
+
+impl Session {
+    /// Om sessionen e så gammel at vi heller logge inn på nytt
+    pub fn isOld(&self, now: DateTime<Utc>) -> bool {
+        now - self.obtained_at > SESSION_MAX_AGE
+    }
+}
+
+pub async fn load(pool: &sqlx::PgPool, brukernavn: &str) -> Option<Session> {
+    sqlx::query_as("SELECT cookie, obtained_at, validated_at FROM tp_sessions WHERE brukernavn = $1")
+        .bind(brukernavn)
+        .fetch_optional(pool)
+        .await
+        .unwrap()
+}
+
+/// Lagre en ny cookie rett etter en innlogging
+pub async fn save(pool: &sqlx::PgPool, brukernavn: &str, cookie: &str) {
+    sqlx::query("INSERT INTO tp_sessions (brukernavn, cookie, obtained_at, validated_at) VALUES ($1, $2, now(), now())
+        ON CONFLICT (brukernavn) DO UPDATE SET cookie = $2, obtained_at = now(), validated_at = now()")
+        .bind(brukernavn)
+        .bind(cookie)
+        .execute(pool)
+        .await
+        .unwrap();
+}
+
+/// TP godtok cookien akkurat nå
+pub async fn markValidated(pool: &sqlx::PgPool, brukernavn: &str) {
+    sqlx::query("UPDATE tp_sessions SET validated_at = now() WHERE brukernavn = $1")
+        .bind(brukernavn)
+        .execute(pool)
+        .await
+        .unwrap();
+}
+
+fn wakeup() -> &'static Notify {
+    static WAKEUP: OnceLock<Notify> = OnceLock::new();
+    WAKEUP.get_or_init(Notify::new)
+}
+
+/// Be refreshSessions sjekk alle sessionan nå i stedet for å vent, f.eks. fordi nettsida fant en død session
+pub fn requestRefresh() {
+    wakeup().notify_one();
+}
+
+/// Holde sessionan te alle kontoan i live så lenge servern kjøre. Kontoa uten session eller med en gammel session
+/// logges inn på nytt, dem andre sjekkes med getBookings, som også logge inn på nytt om TP ikkje godtar cookien.
+pub async fn refreshSessions(groups: &[Group], pool: &sqlx::PgPool) {
+    loop {
+        let mut checked = HashSet::new();
+        for group in groups {
+            for account in &group.accounts {
+                if !checked.insert(account.brukernavn.clone()) {
+                    continue;
+                }
+                let result = match load(pool, &account.brukernavn).await {
+                    Some(session) if !session.isOld(Utc::now()) =>
+                        getClientAndBookings(account, &group.policy, pool, Login::Inline).await.map(|_| ()),
+                    _ => newClient(account, &group.policy, pool).await.map(|_| ()),
+                };
+                if let Err(e) = result {
+                    println!("Fikk ikkje fornya sessionen te {}: {}", account.brukernavn, e);
+                }
+            }
+        }
+        let _ = tokio::time::timeout(SESSION_CHECK_INTERVAL.to_std().unwrap(), wakeup().notified()).await;
+    }
+}
@@ -0,0 +1,522 @@
+use std::sync::OnceLock;
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use reqwest::{Client, StatusCode};
+use serde::{de::{DeserializeOwned, Error as _}, Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+
+use crate::fixtures::RecordReplay;
+use crate::planner;
+
+const DEFAULT_TP_URL: &str = "https://tp.educloud.no";
+
+static TP_URL: OnceLock<String> = OnceLock::new();
+
+/// Bytt TP adressen, f.eks. te mock TP på localhost. Må gjøres før første request, og bare én gang.
+pub fn setBaseUrl(url: &str) {
+    TP_URL.set(url.trim_end_matches('/').to_string()).expect("TP adressen e allerede satt");
+}
+
+#[cfg(test)]
+thread_local! {
+    /// Testan kjøre mot sin egen mock TP, uten å røre adressen dem andre testan bruke
+    static TEST_URL: std::cell::Cell<Option<&'static str>> = const { std::cell::Cell::new(None) };
+}
+
+/// Bruk url som TP adressen for resten av testen
+#[cfg(test)]
+pub fn setBaseUrlForTest(url: &str) {
+    let url: &'static str = Box::leak(url.trim_end_matches('/').to_string().into_boxed_str());
+    TEST_URL.with(|testUrl| testUrl.set(Some(url)));
+}
+
+/// Adressen te TP, uten institusjon og uten / på slutten
+pub fn baseUrl() -> &'static str {
+    #[cfg(test)]
+    if let Some(url) = TEST_URL.with(|testUrl| testUrl.get()) {
+        return url;
+    }
+    TP_URL.get().map(|url| url.as_str()).unwrap_or(DEFAULT_TP_URL)
+}
+
+/// Adressen te TP for en institusjon, som https://tp.educloud.no/ntnu
+pub fn institutionUrl(institusjon: &str) -> String {
+    format!("{}/{}", baseUrl(), institusjon)
+}
+
+/// Et rom sånn TP kjenne det igjen. Institusjonen står i adressen, og TP vil ha byggkoden foran romnavnet
+/// (250R1 for R1 på Helgasetr). Romnavnet alene e bare unikt innafor et bygg.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RoomId {
+    pub institusjon: String,
+    pub bygg: String,
+    pub rom: String,
+}
+
+impl RoomId {
+    /// Id-en TP bruke i room.php og reservation.php
+    pub fn tpId(&self) -> String {
+        format!("{}{}", self.bygg, self.rom)
+    }
+}
+
+impl std::fmt::Display for RoomId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}/{}", self.institusjon, self.bygg, self.rom)
+    }
+}
+
+// Typene under e sånn TP sine JSON svar ser ut. Felta vi ikkje bruke tar vi ikkje med,
+// sånn at TP kan legg te ting uten at vi bryr oss. Mangle et felt vi treng får vi en feil i stedet for en panic.
+
+/// En av våre egne bookinga fra bookings.php. Lagres også i key_value, så vi har nåkka å vis når TP e nede.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TpBooking {
+    /// TP e ikkje konsekvent på om id-en e tall eller tekst, så vi gjør den alltid te tekst
+    #[serde(deserialize_with = "idFraTallEllerTekst")]
+    pub id: String,
+    pub name: String,
+    /// Når bookinga starte, som "2025-01-20 08:30:00"
+    #[serde(deserialize_with = "tpTidspunkt", serialize_with = "tpTidspunktTilTekst")]
+    pub booked: NaiveDateTime,
+    pub firstday: NaiveDate,
+    /// Klokkeslettan, som "08:30 - 12:30"
+    pub tid: String,
+    pub rooms: Vec<TpBookingRoom>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TpBookingRoom {
+    pub name: String,
+}
+
+impl TpBooking {
+    /// Navnet på rommet, TP gir oss ei liste men vi booke aldri mer enn ett
+    pub fn room(&self) -> &str {
+        self.rooms.first().map(|room| room.name.as_str()).unwrap_or("")
+    }
+
+    /// Slutten på bookinga, om tid ser ut som forventa
+    pub fn end(&self) -> Option<NaiveDateTime> {
+        let end = self.tid.rsplit('-').next()?.trim().parse::<NaiveTime>().ok()?;
+        Some(self.booked.date().and_time(end))
+    }
+}
+
+/// Svaret fra room.php, timeplanen te et rom
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TpRoomSchedule {
+    pub events: Vec<TpRoomEvent>,
+}
+
+/// Én ting som skjer i et rom. summary e navnet på bookinga eller emnet.
+/// Lagres også i room_schedules, så det må kunna skrives tebake som JSON.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TpRoomEvent {
+    #[serde(default)]
+    pub summary: String,
+    #[serde(deserialize_with = "tpTidssone")]
+    pub dtstart: DateTime<Utc>,
+    #[serde(deserialize_with = "tpTidssone")]
+    pub dtend: DateTime<Utc>,
+}
+
+/// Det vi poste te reservation.php for å book et rom
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TpReservationRequest {
+    pub start: NaiveDateTime,
+    pub end: NaiveDateTime,
+    /// RoomId::tpId te romman
+    pub rooms: Vec<String>,
+    pub name: String,
+    pub notes: String,
+    pub userGroup: Option<String>,
+}
+
+/// Det TP svare når den godtar en reservasjon. Vi vet ikkje heilt ka som kommer, så alt e valgfritt.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct TpReservationResponse {
+    #[serde(default, deserialize_with = "valgfriId")]
+    pub id: Option<String>,
+    #[serde(default)]
+    pub message: Option<String>,
+}
+
+impl std::fmt::Display for TpReservationResponse {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (&self.id, &self.message) {
+            (Some(id), _) => write!(f, "booka med id {}", id),
+            (None, Some(message)) => write!(f, "{}", message),
+            (None, None) => write!(f, "booka"),
+        }
+    }
+}
+
+fn idFraTallEllerTekst<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    match Value::deserialize(deserializer)? {
+        Value::String(id) => Ok(id),
+        Value::Number(id) => Ok(id.to_string()),
+        id => Err(D::Error::custom(format!("ugyldig id {}", id))),
+    }
+}
+
+fn valgfriId<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    match Option::<Value>::deserialize(deserializer)? {
+        None | Some(Value::Null) => Ok(None),
+        Some(id) => idFraTallEllerTekst(id).map(Some).map_err(D::Error::custom),
+    }
+}
+
+/// Tidspunkt uten tidssone som "2025-01-20 08:30:00"
+fn tpTidspunkt<'de, D: Deserializer<'de>>(deserializer: D) -> Result<NaiveDateTime, D::Error> {
+    let tid = String::deserialize(deserializer)?;
+    NaiveDateTime::parse_from_str(&tid, "%F %T").map_err(|e| D::Error::custom(format!("{}: {}", tid, e)))
+}
+
+fn tpTidspunktTilTekst<S: Serializer>(tid: &NaiveDateTime, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(&tid.format("%F %T"))
+}
+
+/// Tidspunkt med tidssone. TP skriv "+01:00", men vi tåle "+0100" også.
+fn tpTidssone<'de, D: Deserializer<'de>>(deserializer: D) -> Result<DateTime<Utc>, D::Error> {
+    let tid = String::deserialize(deserializer)?;
+    DateTime::parse_from_rfc3339(&tid)
+        .or_else(|_| DateTime::parse_from_str(&tid, "%FT%T%#z"))
+        .map(|t| t.with_timezone(&Utc))
+        .map_err(|e| D::Error::custom(format!("{}: {}", tid, e)))
+}
+
+
+/// Alt som kan gå galt når vi snakke med TP
+#[derive(Debug)]
+pub enum TpError {
+    /// Vi fikk ikkje kontakt med TP i det hele tatt
+    Network(reqwest::Error),
+    /// Cookien har gått ut, og TP sende oss te innlogginga i stedet for å svar
+    AuthExpired,
+    /// TP svarte, men ikkje med nåkka vi skjønte
+    UnexpectedPayload(String),
+    /// TP skjønte requesten, men sa nei
+    Refused(ReservationError),
+    /// Vi avbestilte bookinga for å flytt den, men fikk verken booka den nye eller den gamle tebake
+    BookingLost(String, Box<TpError>),
+}
+
+impl std::fmt::Display for TpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TpError::Network(e) => write!(f, "nettverksfeil: {}", e),
+            TpError::AuthExpired => write!(f, "innlogginga har gått ut"),
+            TpError::UnexpectedPayload(msg) => write!(f, "uventa svar: {}", msg),
+            TpError::Refused(e) => write!(f, "{}", e),
+            TpError::BookingLost(booking, e) => write!(f, "mista {} da vi prøvde å flytt den: {}", booking, e),
+        }
+    }
+}
+
+impl From<reqwest::Error> for TpError {
+    fn from(e: reqwest::Error) -> TpError {
+        TpError::Network(e)
+    }
+}
+
+/// Grunnen til at TP ikkje ville gi oss et rom
+#[derive(Debug)]
+pub enum ReservationError {
+    /// Brukeren har nådd grensa for hvor mange bookinga den kan ha
+    Quota(String),
+    /// Rommet ble booka av nån andre før oss
+    Conflict(String),
+    /// Brukeren har ikkje lov te å book dette rommet
+    Permission(String),
+    /// TP sa nei, men vi skjønne ikkje heilt hvorfor
+    Refused(String),
+}
+
+impl std::fmt::Display for ReservationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReservationError::Quota(msg) => write!(f, "kvoten e full: {}", msg),
+            ReservationError::Conflict(msg) => write!(f, "rommet e opptatt: {}", msg),
+            ReservationError::Permission(msg) => write!(f, "mangle tilgang: {}", msg),
+            ReservationError::Refused(msg) => write!(f, "avvist: {}", msg),
+        }
+    }
+}
+
+impl ReservationError {
+    /// TP gir oss ikkje nån feilkoder vi kan stol på, så vi gjette ut fra statuskoden og teksten i svaret
+    fn fromResponse(status: StatusCode, body: &str) -> ReservationError {
+        let json: Value = serde_json::from_str(body).unwrap_or(Value::Null);
+        let msg = ["error", "message", "msg"].iter()
+            .find_map(|key| json.get(key).and_then(|v| v.as_str()))
+            .unwrap_or(body)
+            .to_string();
+        let lower = msg.to_lowercase();
+
+        // Kvoten sjekkes først, for meldinga om full kvote kan også inneholde "allerede" og "ikke lov".
+        // Bomme vi her prøve bookRooms alle romman med en full bruker i stedet for å bytt bruker.
+        if ["kvote", "quota", "maks", "limit", "grense", "for mange", "too many", "ha flere", "more than"].iter().any(|k| lower.contains(k))
+            || nevnerAntallBookinga(&lower) {
+            ReservationError::Quota(msg)
+        } else if status == StatusCode::FORBIDDEN || ["tilgang", "permission", "not allowed", "ikke lov", "ikkje lov"].iter().any(|k| lower.contains(k)) {
+            ReservationError::Permission(msg)
+        } else if status == StatusCode::CONFLICT || ["opptatt", "ikke ledig", "allerede", "conflict", "occupied", "already", "not available"].iter().any(|k| lower.contains(k)) {
+            ReservationError::Conflict(msg)
+        } else {
+            ReservationError::Refused(msg)
+        }
+    }
+}
+
+/// Om meldinga sier hvor mange bookinga brukeren har, som "Du har allerede 8 bookinger"
+fn nevnerAntallBookinga(lower: &str) -> bool {
+    let words: Vec<&str> = lower.split_whitespace().collect();
+    words.windows(2).any(|w| w[0].parse::<u32>().is_ok() && ["booking", "reservasjon", "reservation"].iter().any(|b| w[1].starts_with(b)))
+}
+
+/// Om TP har sendt oss te innlogginga i stedet for å svar. Da e vi enten redirecta vekk fra /ws/, eller får en html side.
+fn isLoggedOut(res: &reqwest::Response) -> bool {
+    let isHtml = res.headers().get(reqwest::header::CONTENT_TYPE)
+        .and_then(|t| t.to_str().ok())
+        .is_some_and(|t| t.contains("text/html"));
+    res.status() == StatusCode::UNAUTHORIZED || !res.url().path().contains("/ws/") || isHtml
+}
+
+/// Les et svar fra TP som JSON av typen T
+async fn readJson<T: DeserializeOwned>(res: Result<reqwest::Response, reqwest::Error>) -> Result<T, TpError> {
+    let res = res?;
+    if isLoggedOut(&res) {
+        return Err(TpError::AuthExpired);
+    }
+    let status = res.status();
+    let body = res.text().await?;
+    if !status.is_success() {
+        return Err(TpError::UnexpectedPayload(format!("status {}: {}", status, kortVersjon(&body))));
+    }
+    serde_json::from_str(&body).map_err(|e| TpError::UnexpectedPayload(format!("{}: {}", e, kortVersjon(&body))))
+}
+
+/// Gjør et svar på en reservasjon om til Ok med JSON om TP godtok requesten, ellers en TpError
+async fn checkResponse(res: Result<reqwest::Response, reqwest::Error>) -> Result<Value, TpError> {
+    let res = res?;
+    if isLoggedOut(&res) {
+        return Err(TpError::AuthExpired);
+    }
+    let status = res.status();
+    let body = res.text().await?;
+
+    // TP svare med JSON når det gikk bra, og enten en feilstatus eller en error/message når det ikkje gjorde det
+    match serde_json::from_str::<Value>(&body) {
+        Ok(json) if status.is_success() && json.get("error").is_none() => Ok(json),
+        _ if status.is_success() && body.trim().is_empty() => Ok(Value::Null),
+        _ => Err(TpError::Refused(ReservationError::fromResponse(status, &body))),
+    }
+}
+
+/// Starten av et svar, sånn at en heil html side ikkje havne i loggen
+fn kortVersjon(body: &str) -> &str {
+    body.char_indices().nth(200).map(|(i, _)| &body[..i]).unwrap_or(body)
+}
+
+// API hjelpemetoder
+pub trait ClientMethods {
+    // async fn login(&self, secretStore: &SecretStore, pool: &sqlx::PgPool);
+    async fn getBookings(&self, institusjon: &str) -> Result<Vec<TpBooking>, TpError>;
+    async fn getScheduleForRoom(&self, room: &RoomId, startDate: NaiveDate, endDate: NaiveDate) -> Result<TpRoomSchedule, TpError>;
+    async fn getRoomCatalog(&self, institusjon: &str) -> Result<Value, TpError>;
+    async fn bookRoom(&self, name: &str, room: &RoomId, start: &NaiveDateTime, end: &NaiveDateTime) -> Result<TpReservationResponse, TpError>;
+    async fn cancelBooking(&self, institusjon: &str, bookingId: &str) -> Result<(), TpError>;
+    async fn modifyBooking(&self, booking: &TpBooking, room: &RoomId, start: &NaiveDateTime, end: &NaiveDateTime) -> Result<TpReservationResponse, TpError>;
+}
+
+impl ClientMethods for Client {
+    /// Skaffe våre egne bookinga
+    async fn getBookings(&self, institusjon: &str) -> Result<Vec<TpBooking>, TpError> {
+        readJson(self.get(format!("{}/ws/rombestilling/bookings.php", institutionUrl(institusjon)))
+                .header("accept", "application/json")
+                .sendRecorded().await).await
+    }
+
+    /// Skaffe timeplan for et spesifikt rom
+    async fn getScheduleForRoom(&self, room: &RoomId, startDate: NaiveDate, endDate: NaiveDate) -> Result<TpRoomSchedule, TpError> {
+        readJson(self.get(format!("{}/ws/1.4/room.php?id={}&fromdate={}&todate={}&lang=no&split_intervals=false",
+                institutionUrl(&room.institusjon), room.tpId(), startDate.format("%F"), endDate.format("%F")
+            ))
+            .header("accept", "application/json")
+            .sendRecorded().await).await
+    }
+
+    /// Skaffe alle romman TP vet om, det samme som romsøket på rombestillingssida bruke
+    async fn getRoomCatalog(&self, institusjon: &str) -> Result<Value, TpError> {
+        readJson(self.get(format!("{}/ws/rombestilling/rooms.php", institutionUrl(institusjon)))
+            .header("accept", "application/json")
+            .sendRecorded().await).await
+    }
+
+    /// Booke faktisk rom på det tidspunktet. Gir Ok med svaret fra TP bare om TP faktisk godtok reservasjonen.
+    async fn bookRoom(&self, name: &str, room: &RoomId, start: &NaiveDateTime, end: &NaiveDateTime) -> Result<TpReservationResponse, TpError>{
+        let request = TpReservationRequest {
+            start: *start,
+            end: *end,
+            rooms: vec![room.tpId()],
+            name: name.to_string(),
+            notes: String::new(),
+            userGroup: None,
+        };
+        let res = self.post(format!("{}/ws/rombestilling/reservation.php", institutionUrl(&room.institusjon)))
+            .header("accept", "application/json")
+            .body(serde_json::to_string(&request).unwrap())
+            .sendRecorded().await;
+
+        // TP godtok reservasjonen, så et svar vi ikkje kjenne igjen e ikkje en feil
+        checkResponse(res).await.map(|json| serde_json::from_value(json).unwrap_or_default())
+    }
+
+    /// Avbestill en av våre bookinga, sånn at rommet bli ledig for andre
+    async fn cancelBooking(&self, institusjon: &str, bookingId: &str) -> Result<(), TpError> {
+        let res = self.delete(format!("{}/ws/rombestilling/reservation.php?id={}", institutionUrl(institusjon), bookingId))
+            .header("accept", "application/json")
+            .sendRecorded().await;
+
+        checkResponse(res).await.map(|_| ())
+    }
+
+    /// Flytt en booking te et anna rom eller tidspunkt. Vi vet ikkje om nå endepunkt i TP for å endre en reservasjon,
+    /// så vi booke den nye først og avbestille den gamle etterpå, sånn at vi aldri står uten rom om nåkka går galt.
+    /// Bare om kvoten e full, eller TP sier rommet e opptatt og det bare e den gamle bookinga som overlappe,
+    /// må den gamle avbestilles først. Går ikkje den nye da booke vi den gamle på nytt.
+    /// Bookinga må vær i samme institusjon og bygg som room.
+    async fn modifyBooking(&self, booking: &TpBooking, room: &RoomId, start: &NaiveDateTime, end: &NaiveDateTime) -> Result<TpReservationResponse, TpError> {
+        let oldRoom = RoomId { rom: booking.room().to_string(), ..room.clone() };
+        let oldEnd = booking.end().unwrap_or(booking.booked);
+        let overlapsOld = oldRoom == *room && booking.booked < *end && *start < oldEnd;
+        let institusjon = &room.institusjon;
+
+        let reason = match self.bookRoom(&booking.name, room, start, end).await {
+            Ok(response) => {
+                if let Err(e) = self.cancelBooking(institusjon, &booking.id).await {
+                    // Avbestill den nye igjen, sånn at vi ikkje sitte med begge. Svare ikkje TP med id-en lete vi den opp.
+                    let id = match &response.id {
+                        Some(id) => Some(id.clone()),
+                        None => findBookingId(self, room, start).await,
+                    };
+                    let rollback = match id {
+                        Some(id) => self.cancelBooking(institusjon, &id).await.map_err(|e| e.to_string()),
+                        None => Err("fant ikkje den nye bookinga".to_string()),
+                    };
+                    if let Err(rollback) = rollback {
+                        println!("Sitte nå med både {} og den nye i {} {}: {}", booking.id, room, start, rollback);
+                    }
+                    return Err(e);
+                }
+                return Ok(response);
+            },
+            Err(TpError::Refused(reason)) => reason,
+            Err(e) => return Err(e),
+        };
+
+        let cancelFirst = match reason {
+            ReservationError::Quota(_) => true,
+            ReservationError::Conflict(_) if overlapsOld => onlyOldOverlaps(self, booking, oldEnd, room, start, end).await,
+            _ => false,
+        };
+        if !cancelFirst {
+            return Err(TpError::Refused(reason));
+        }
+
+        self.cancelBooking(institusjon, &booking.id).await?;
+        match self.bookRoom(&booking.name, room, start, end).await {
+            Ok(response) => Ok(response),
+            Err(e) => {
+                // Den gamle va ledig for et øyeblikk sia, så prøv å få den tebake
+                match self.bookRoom(&booking.name, &oldRoom, &booking.booked, &oldEnd).await {
+                    Ok(_) => Err(e),
+                    Err(rebook) => Err(TpError::BookingLost(format!("{} {}", booking.room(), booking.booked), Box::new(rebook))),
+                }
+            },
+        }
+    }
+}
+
+/// Id-en te vår booking i rommet som starte på start, for når TP ikkje sende den med i svaret på reservasjonen
+async fn findBookingId(client: &Client, room: &RoomId, start: &NaiveDateTime) -> Option<String> {
+    let bookings = client.getBookings(&room.institusjon).await.ok()?;
+    bookings.into_iter()
+        .find(|b| b.room() == room.rom && b.booked == *start)
+        .map(|b| b.id)
+}
+
+/// Om bookinga vi vil flytt e det eneste i room som overlappe med det nye tidsrommet.
+/// Da va det oss sjøl TP mente rommet va opptatt av. Får vi ikkje sjekka det går vi ut fra at det va nån andre.
+async fn onlyOldOverlaps(client: &Client, booking: &TpBooking, oldEnd: NaiveDateTime, room: &RoomId, start: &NaiveDateTime, end: &NaiveDateTime) -> bool {
+    let Ok(schedule) = client.getScheduleForRoom(room, start.date(), end.date()).await else { return false };
+    let (start, end) = (planner::osloTilUtc(start), planner::osloTilUtc(end));
+    let (oldStart, oldEnd) = (planner::osloTilUtc(&booking.booked), planner::osloTilUtc(&oldEnd));
+    schedule.events.iter()
+        .filter(|event| planner::overlapper(&event.dtstart, &event.dtend, &start, &end))
+        .all(|event| event.dtstart == oldStart && event.dtend == oldEnd)
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::{ResponseBuilderExt, Url};
+
+    use super::*;
+
+    fn kind(e: &ReservationError) -> &'static str {
+        match e {
+            ReservationError::Quota(_) => "Quota",
+            ReservationError::Conflict(_) => "Conflict",
+            ReservationError::Permission(_) => "Permission",
+            ReservationError::Refused(_) => "Refused",
+        }
+    }
+
+    #[test]
+    fn feilmeldingeneKjennesIgjen() {
+        let cases = [
+            (400, "Du har allerede 8 bookinger", "Quota"),
+            (403, "Det er ikke lov å ha flere reservasjoner", "Quota"),
+            (400, r#"{"error": "Maks antall bookinger er nådd"}"#, "Quota"),
+            (400, r#"{"message": "Booking limit reached"}"#, "Quota"),
+            (409, r#"{"error": "Rommet er allerede opptatt"}"#, "Conflict"),
+            (409, "", "Conflict"),
+            (400, "Room is not available", "Conflict"),
+            (403, "", "Permission"),
+            (400, r#"{"msg": "Du har ikke tilgang til dette rommet"}"#, "Permission"),
+            (400, r#"{"error": "Ugyldig tidspunkt"}"#, "Refused"),
+        ];
+        for (status, body, expected) in cases {
+            let e = ReservationError::fromResponse(StatusCode::from_u16(status).unwrap(), body);
+            assert_eq!(kind(&e), expected, "{} {}", status, body);
+        }
+    }
+
+    fn response(status: u16, contentType: &str, path: &str, body: &str) -> Result<reqwest::Response, reqwest::Error> {
+        let response = http::Response::builder()
+            .status(status)
+            .url(Url::parse(&format!("https://tp.educloud.no{}", path)).unwrap())
+            .header("content-type", contentType)
+            .body(body.to_string())
+            .unwrap();
+        Ok(reqwest::Response::from(response))
+    }
+
+    #[tokio::test]
+    async fn reservasjonssvaran() {
+        let ws = "/ntnu/ws/rombestilling/reservation.php";
+        let json = "application/json";
+
+        assert_eq!(checkResponse(response(200, json, ws, r#"{"id": 5}"#)).await.unwrap()["id"], 5);
+        assert_eq!(checkResponse(response(204, json, ws, "")).await.unwrap(), Value::Null);
+        assert!(matches!(checkResponse(response(200, "text/html; charset=utf-8", ws, "<html>Logg inn</html>")).await, Err(TpError::AuthExpired)));
+        assert!(matches!(checkResponse(response(200, json, "/ntnu/", "{}")).await, Err(TpError::AuthExpired)));
+        assert!(matches!(checkResponse(response(401, json, ws, "")).await, Err(TpError::AuthExpired)));
+
+        let refused = |res| async { match checkResponse(res).await { Err(TpError::Refused(e)) => kind(&e), other => panic!("{:?}", other) } };
+        assert_eq!(refused(response(200, json, ws, r#"{"error": "Rommet er opptatt"}"#)).await, "Conflict");
+        assert_eq!(refused(response(400, json, ws, r#"{"error": "Du har allerede 8 bookinger"}"#)).await, "Quota");
+        assert_eq!(refused(response(500, "text/plain", ws, "Internal Server Error")).await, "Refused");
+    }
+}
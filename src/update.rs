@@ -19,6 +19,8 @@ use chrono_tz::Europe::Oslo;
 
 use reqwest::Method;
 
+use crate::fixtures::{self, RecordReplay};
+
 use tokio::task;
 
 use lettre::transport::smtp::authentication::Credentials;
@@ -210,7 +212,7 @@ impl EventProps  {
     }
 
     async fn makeEventsFromIcal(client: &reqwest::Client, icalLink: &str, livsdelEmoji: &char) -> Vec<EventProps> {
-        let resp = client.get(icalLink).send()
+        let resp = client.get(icalLink).sendRecordedSecret()
             .await.expect("icalResponse").text().await.expect("icalContent");
 
         let cal: IcalCalendar = ical::IcalParser::new(resp.as_bytes()).next()
@@ -223,17 +225,17 @@ impl EventProps  {
 
             match startEnd {
                 StartEnd::Date(startDate) => {
-                    if startDate < Utc::now().naive_utc().date() - Duration::days(1) {
+                    if startDate < fixtures::now().naive_utc().date() - Duration::days(1) {
                         continue
                     }
                 },
                 StartEnd::DateDate(startDate, _) => {
-                    if startDate < Utc::now().naive_utc().date() - Duration::days(1) {
+                    if startDate < fixtures::now().naive_utc().date() - Duration::days(1) {
                         continue
                     }
                 },
                 StartEnd::DateTime(startDateTime, _) => {
-                    if startDateTime.naive_utc() < Utc::now().naive_utc() - Duration::days(1) {
+                    if startDateTime.naive_utc() < fixtures::now().naive_utc() - Duration::days(1) {
                         continue
                     }
                 }
@@ -376,7 +378,7 @@ impl NotionClient {
         json!({
             "property": "Dato",
             "date": {
-                "on_or_after": Utc::now().naive_utc().date() - Duration::days(1)
+                "on_or_after": fixtures::now().naive_utc().date() - Duration::days(1)
             }
         })
     }
@@ -395,7 +397,7 @@ impl NotionClient {
             .header("Notion-Version", "2022-06-28")
             .header("Content-Type", "application/json")
             .body(body.to_string())
-            .send().await
+            .sendRecorded().await
             .expect("Response plz")
             .json().await
             .expect("JSON plz");
@@ -667,3 +669,43 @@ pub async fn update(
     
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secrets(pairs: &[(&str, &str)]) -> SecretStore {
+        let map: serde_json::Map<String, Value> = pairs.iter().map(|(k, v)| (k.to_string(), v.to_string().into())).collect();
+        serde_json::from_value(map.into()).unwrap()
+    }
+
+    /// Spille av fixtures/test/update, som ble tatt opp mandag 2027-01-11 07:00. Notion har p1 med gammelt klokkeslett
+    /// og p2 som ikkje lenger e i kalendern. Kalendern har p1 sin event flytta, en ny event, og en fra nyttår som e for gammel.
+    #[tokio::test]
+    async fn oppdaterNotionFraOpptak() {
+        fixtures::replayForTest(rocket::fs::relative!("fixtures/test/update"));
+        let secretStore = secrets(&[
+            ("NOTION_API_TOKEN", "token"),
+            ("INTEGRATION_USER_ID", "bot"),
+            ("TRACKING_DATABASE", "db"),
+            ("ICAL_LINKS", "https://kalender.example/ical/hemmelig/basic.ics"),
+            ("LIVSDEL_IDS", "livsdel"),
+        ]);
+
+        update(&secretStore).await.unwrap();
+
+        let mut writes: Vec<_> = fixtures::replayedRequests().into_iter()
+            .filter(|request| request.starts_with("POST") || request.starts_with("PATCH"))
+            .collect();
+        writes.sort();
+        assert_eq!(writes, [
+            "PATCH https://api.notion.com/v1/pages/p1",
+            "PATCH https://api.notion.com/v1/pages/p2",
+            "POST https://api.notion.com/v1/comments",
+            "POST https://api.notion.com/v1/comments",
+            "POST https://api.notion.com/v1/databases/db/query",
+            "POST https://api.notion.com/v1/pages",
+        ]);
+        assert!(fixtures::replayedRequests().contains(&"GET https://kalender.example/".to_string()));
+    }
+}